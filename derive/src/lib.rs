@@ -0,0 +1,127 @@
+//! Derive macros for wgpu-explorer.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Fields, Ident, Lit,
+    Meta, NestedMeta, Result,
+};
+
+/// Implement `VertexLayout` for a `#[repr(C)]` struct with named fields.
+///
+/// Each field becomes an attribute at the next shader location, from 0 in field order,
+/// read as its type's `VertexField::FORMAT`.
+/// `#[vertex(format = "Unorm8x4")]` on a field reads it as another `wgpu::VertexFormat`,
+/// such as normalized colours stored as bytes.
+///
+/// The generated impl refers to `crate::vertex_layout`, so it only works inside wgpu-explorer.
+#[proc_macro_derive(VertexLayout, attributes(vertex))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    vertex_layout(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn vertex_layout(input: &DeriveInput) -> Result<TokenStream2> {
+    if !is_repr_c(&input.attrs) {
+        return Err(Error::new(
+            input.ident.span(),
+            "VertexLayout needs #[repr(C)], so fields stay in the order they're declared",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    data.fields.span(),
+                    "VertexLayout needs named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "VertexLayout can only be derived for structs",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut attributes = vec![];
+    for (location, field) in fields.iter().enumerate() {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        let location = location as u32;
+
+        let format = match format_override(&field.attrs)? {
+            Some(format) => quote! { ::wgpu::VertexFormat::#format },
+            None => quote! { <#ty as crate::vertex_layout::VertexField>::FORMAT },
+        };
+
+        attributes.push(quote! {
+            ::wgpu::VertexAttribute {
+                format: #format,
+                offset: ::std::mem::offset_of!(#name #type_generics, #ident) as ::wgpu::BufferAddress,
+                shader_location: #location,
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics crate::vertex_layout::VertexLayout for #name #type_generics #where_clause {
+            const ATTRIBUTES: &'static [::wgpu::VertexAttribute] = &[#(#attributes),*];
+        }
+    })
+}
+
+fn is_repr_c(attrs: &[Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("repr"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .any(|nested| matches!(nested, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("C")))
+}
+
+/// The format given by `#[vertex(format = "...")]`, if any.
+fn format_override(attrs: &[Attribute]) -> Result<Option<Ident>> {
+    let mut format = None;
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("vertex")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(Error::new(
+                    meta.span(),
+                    "expected #[vertex(format = \"...\")]",
+                ))
+            }
+        };
+
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(name_value))
+                    if name_value.path.is_ident("format") =>
+                {
+                    match name_value.lit {
+                        Lit::Str(lit) => format = Some(Ident::new(&lit.value(), lit.span())),
+                        lit => return Err(Error::new(lit.span(), "expected a string")),
+                    }
+                }
+                nested => return Err(Error::new(nested.span(), "expected format = \"...\"")),
+            }
+        }
+    }
+
+    Ok(format)
+}
@@ -0,0 +1,809 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use color_eyre::{eyre::ContextCompat, Result};
+use log::info;
+use wgpu::Device;
+use winit::{
+    dpi::PhysicalSize,
+    event::{DeviceEvent, ElementState, Event, WindowEvent},
+    window::WindowId,
+};
+
+use crate::{
+    app_window::{self, AppWindow, Context},
+    bindings::{Action, Bindings, Scope},
+    camera_path::{CameraPath, Keyframe},
+    globals::GlobalsUniform,
+    gpu::DeviceContext,
+    input::InputState,
+    pipeline::PipelineCache,
+    profiler::{GpuTimer, Phase, Profiler},
+    recording::{
+        InputEvent, RecordedInput, RecordedState, Recorder, Recording, Replay, Replayed, Target,
+    },
+    resources,
+    scene::Scene,
+    scheduler::Scheduler,
+    screenshot,
+    session::Maps,
+};
+
+/// Where recordings are written to and replayed from.
+const RECORDING_PATH: &str = "recording.ron";
+
+/// Where camera paths are saved to and loaded from.
+const CAMERA_PATH_PATH: &str = "camera_path.ron";
+
+/// Where frame timings are saved to.
+const PROFILE_PATH: &str = "profile.csv";
+
+/// How often the frame timings in the window titles change.
+const TITLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A change to a window's surface, waiting for the device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SurfaceChange {
+    CyclePresentMode,
+    CycleFormat,
+}
+
+/// The open windows and everything deciding what they show.
+pub struct App {
+    pub windows: HashMap<WindowId, Box<dyn AppWindow>>,
+    pub scene: Scene,
+
+    pub scheduler: Scheduler,
+    pub input: InputState,
+
+    /// The window with keyboard focus, whose scope held keys act in
+    pub focused: Option<Target>,
+
+    pub bindings: Bindings,
+    pub bindings_path: PathBuf,
+    pub show_help: bool,
+    pub show_inspector: bool,
+
+    pub recorder: Option<Recorder>,
+    pub replay: Option<Replay>,
+
+    /// How far along the camera path we are, if flying along it
+    pub path_distance: Option<f32>,
+
+    pub screenshot_dir: PathBuf,
+
+    /// Where the square's mesh is saved to and loaded from
+    pub layout_path: PathBuf,
+
+    /// The window to take a screenshot of after the next frame
+    pub pending_screenshot: Option<WindowId>,
+
+    /// Whether closed windows should be opened again
+    pub reopen_windows: bool,
+
+    /// The window whose surface to change before the next frame
+    pub pending_surface_change: Option<(WindowId, SurfaceChange)>,
+
+    /// Whether to start over on a new device
+    pub rebuild_gpu: bool,
+
+    /// Maps to rebuild every window from
+    pub pending_maps: Option<Maps>,
+
+    pub profiler: Profiler,
+
+    /// When frame timings were last put in the window titles
+    titles_updated: Option<Instant>,
+}
+
+impl App {
+    pub fn new(
+        scene: Scene,
+        scheduler: Scheduler,
+        screenshot_dir: PathBuf,
+        layout_path: PathBuf,
+        bindings_path: PathBuf,
+    ) -> Self {
+        let bindings = Bindings::load(&bindings_path).unwrap_or_else(|e| {
+            eprintln!(
+                "Error loading bindings from {}, using defaults: {:#?}",
+                bindings_path.display(),
+                e
+            );
+            Bindings::default()
+        });
+
+        Self {
+            windows: HashMap::new(),
+            scene,
+            scheduler,
+            input: InputState::default(),
+            focused: None,
+            bindings,
+            bindings_path,
+            show_help: false,
+            show_inspector: false,
+            recorder: None,
+            replay: None,
+            path_distance: None,
+            screenshot_dir,
+            layout_path,
+            pending_screenshot: None,
+            reopen_windows: false,
+            pending_surface_change: None,
+            rebuild_gpu: false,
+            pending_maps: None,
+            profiler: Profiler::default(),
+            titles_updated: None,
+        }
+    }
+
+    pub fn open_window(&mut self, mut window: Box<dyn AppWindow>) -> Result<()> {
+        let window_id = window
+            .window_id()
+            .wrap_err("Headless windows can't be opened")?;
+
+        window.set_help(self.help_lines(window.target()));
+        window.set_inspector(self.show_inspector);
+        self.windows.insert(window_id, window);
+        self.scheduler.request_frame();
+
+        Ok(())
+    }
+
+    /// Returns true if that was the last window.
+    pub fn close_window(&mut self, window_id: WindowId) -> bool {
+        self.windows.remove(&window_id);
+
+        self.windows.is_empty()
+    }
+
+    /// The first open window of a kind.
+    pub fn window_of(&self, target: Target) -> Option<&dyn AppWindow> {
+        self.windows
+            .values()
+            .find(|window| window.target() == target)
+            .map(|window| window.as_ref())
+    }
+
+    fn window_id_of(&self, target: Target) -> Option<WindowId> {
+        self.windows
+            .iter()
+            .find(|(_, window)| window.target() == target)
+            .map(|(window_id, _)| *window_id)
+    }
+
+    pub fn target(&self, window_id: WindowId) -> Option<Target> {
+        self.windows.get(&window_id).map(|window| window.target())
+    }
+
+    pub fn reload_bindings(&mut self) {
+        match Bindings::load(&self.bindings_path) {
+            Ok(bindings) => {
+                self.bindings = bindings;
+                self.update_help();
+            }
+            Err(e) => eprintln!("Error reloading bindings: {:#?}", e),
+        }
+    }
+
+    /// Let every window pick up a changed file.
+    /// Pipelines built from other shaders stay cached.
+    pub fn reload(&mut self, device: &Device, pipelines: &PipelineCache, path: &Path) {
+        pipelines.invalidate(path);
+
+        for window in self.windows.values_mut() {
+            if let Err(e) = window.reload(device, pipelines) {
+                eprintln!("Error reloading {:?} window: {:#?}", window.target(), e);
+            }
+        }
+
+        self.scheduler.request_frame();
+    }
+
+    pub fn resize(&mut self, window_id: WindowId, device: &Device, size: PhysicalSize<u32>) {
+        if let Some(window) = self.windows.get_mut(&window_id) {
+            window.resize(device, size);
+        }
+    }
+
+    pub fn request_redraw(&self) {
+        for window in self.windows.values() {
+            window.request_redraw();
+        }
+    }
+
+    /// `timer` is only used while the profiler is shown.
+    pub fn render(
+        &mut self,
+        window_id: WindowId,
+        context: DeviceContext,
+        timer: Option<&GpuTimer>,
+    ) -> Result<()> {
+        let timer = timer.filter(|_| self.profiler.enabled);
+
+        match self.windows.get_mut(&window_id) {
+            Some(window) => window.render(context, &self.scene, &mut self.profiler, timer),
+            // Closed since the redraw was requested
+            None => Ok(()),
+        }
+    }
+
+    /// Offer an event to the user interface of the window it is for, before anything else.
+    /// Returns true if the interface took it, so it shouldn't be handled as input.
+    pub fn capture_event(&mut self, event: &Event<()>) -> bool {
+        let window_id = match event {
+            Event::WindowEvent { window_id, .. } => *window_id,
+            _ => return false,
+        };
+
+        // Like any other input, a replay would diverge
+        if self.replay.is_some() {
+            return false;
+        }
+
+        let start = Instant::now();
+        let captured = match self.windows.get_mut(&window_id) {
+            Some(window) => window.capture_event(event),
+            None => false,
+        };
+        self.profiler.record(Phase::Events, start);
+
+        if captured {
+            self.scheduler.request_frame();
+        }
+
+        captured
+    }
+
+    /// Input coming from the event loop, as opposed to a replay.
+    pub fn handle_window_event(&mut self, window_id: WindowId, event: &WindowEvent) {
+        let target = match self.target(window_id) {
+            Some(target) => target,
+            None => return,
+        };
+
+        let start = Instant::now();
+        if let Some(event) = InputEvent::from_window_event(event) {
+            self.handle_live_input(target, event);
+        }
+        self.profiler.record(Phase::Events, start);
+    }
+
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        let start = Instant::now();
+        if let Some(event) = InputEvent::from_device_event(event) {
+            self.handle_live_input(Target::Device, event);
+        }
+        self.profiler.record(Phase::Events, start);
+    }
+
+    fn handle_live_input(&mut self, target: Target, event: InputEvent) {
+        if self.replay.is_some() {
+            // Only allow stopping the replay, anything else would make it diverge
+            if let InputEvent::Key {
+                key,
+                state: ElementState::Pressed,
+            } = event
+            {
+                if let Some(Action::ToggleReplay) = self.bindings.lookup(scope(target), key) {
+                    self.stop_replay(false);
+                }
+            }
+            return;
+        }
+
+        let recorded = RecordedInput::new(event, self.target_size(target));
+        if let Some(recorder) = &mut self.recorder {
+            recorder.input(target, recorded);
+        }
+
+        self.handle_input(target, event);
+    }
+
+    /// Input from either the event loop or a replay.
+    pub fn handle_input(&mut self, target: Target, event: InputEvent) {
+        match event {
+            InputEvent::Key { key, state } => {
+                // Keys only reach the focused window, even if its focus event went missing
+                self.focus(Some(target));
+
+                // Releases and key repeats only matter for held state
+                if !self.input.handle_key(key, state) {
+                    return;
+                }
+
+                if let Some(action) = self.bindings.lookup(scope(target), key) {
+                    self.handle_action(target, action);
+                }
+            }
+
+            InputEvent::FocusGained => self.focus(Some(target)),
+            InputEvent::FocusLost => {
+                if self.focused == Some(target) {
+                    self.focus(None);
+                } else {
+                    self.input.clear();
+                }
+
+                // Button releases outside the window are never seen
+                for window in self.windows.values_mut() {
+                    if window.target() == target {
+                        window.clear_input();
+                    }
+                }
+            }
+
+            _ => {
+                let mut context = Context {
+                    scene: &mut self.scene,
+                    bindings: &self.bindings,
+                    pending_maps: &mut self.pending_maps,
+                    layout_path: &self.layout_path,
+                };
+
+                let mut changed = false;
+                for window in self.windows.values_mut() {
+                    // Input not tied to a window goes to all of them
+                    if target == Target::Device || window.target() == target {
+                        changed |= window.handle_input(event, &mut context);
+                    }
+                }
+
+                if !changed {
+                    return;
+                }
+            }
+        }
+
+        self.scheduler.request_frame();
+    }
+
+    /// Inner size of the window receiving `target`'s input, zero if there is none.
+    fn target_size(&self, target: Target) -> PhysicalSize<u32> {
+        self.windows
+            .values()
+            .find(|window| window.target() == target)
+            .map_or_else(PhysicalSize::default, |window| window.size())
+    }
+
+    fn recorded_state(&self) -> RecordedState {
+        RecordedState {
+            camera_path: self.scene.camera_path.clone(),
+            selected_keyframe: self.scene.selected_keyframe,
+            path_distance: self.path_distance,
+            editing: self.scene.editing,
+            snap: self.scene.snap,
+        }
+    }
+
+    fn restore_recorded_state(&mut self, state: RecordedState) {
+        self.scene.camera_path = state.camera_path;
+        self.scene.selected_keyframe = state.selected_keyframe;
+        self.path_distance = state.path_distance;
+        self.scene.editing = state.editing;
+        self.scene.snap = state.snap;
+    }
+
+    /// Move keyboard focus, forgetting held keys since their releases go elsewhere.
+    fn focus(&mut self, target: Option<Target>) {
+        if self.focused != target {
+            self.focused = target;
+            self.input.clear();
+        }
+    }
+
+    fn handle_action(&mut self, target: Target, action: Action) {
+        match action {
+            Action::CycleFrameMode => self.scheduler.cycle_mode(),
+            Action::ToggleHelp => {
+                self.show_help = !self.show_help;
+                self.update_help();
+            }
+            Action::ToggleInspector => {
+                self.show_inspector = !self.show_inspector;
+
+                if cfg!(not(feature = "inspector")) {
+                    eprintln!("The inspector needs building with `--features inspector`");
+                }
+                for window in self.windows.values_mut() {
+                    window.set_inspector(self.show_inspector);
+                }
+                self.scheduler.request_frame();
+            }
+            Action::ReopenWindows => self.reopen_windows = true,
+            Action::RebuildGpu => self.rebuild_gpu = true,
+            Action::ToggleProfiler => {
+                self.profiler.enabled = !self.profiler.enabled;
+                self.scheduler.request_frame();
+            }
+            Action::PrintResources => {
+                println!("GPU resources:");
+                for line in resources::inventory_lines() {
+                    println!("{}", line);
+                }
+            }
+            Action::SaveProfile => match self.profiler.save_csv(PROFILE_PATH) {
+                Ok(()) => println!("Saved frame timings to {}", PROFILE_PATH),
+                Err(e) => eprintln!("Error saving frame timings: {:#?}", e),
+            },
+
+            // A replay must not start or stop recordings, nor replay itself
+            Action::ToggleRecording | Action::ToggleReplay if self.replay.is_some() => {}
+            Action::ToggleRecording => self.toggle_recording(),
+            Action::ToggleReplay => self.start_replay(),
+
+            Action::Screenshot => self.pending_screenshot = self.window_id_of(target),
+            Action::CyclePresentMode => {
+                self.pending_surface_change = self
+                    .window_id_of(target)
+                    .map(|window_id| (window_id, SurfaceChange::CyclePresentMode));
+            }
+            Action::CycleSurfaceFormat => {
+                self.pending_surface_change = self
+                    .window_id_of(target)
+                    .map(|window_id| (window_id, SurfaceChange::CycleFormat));
+            }
+
+            Action::AddKeyframe => {
+                self.scene
+                    .camera_path
+                    .push(Keyframe::from_camera(&self.scene.camera));
+            }
+            Action::RemoveKeyframe => {
+                let selected = self.scene.selected_keyframe.take();
+                let last = self.scene.camera_path.keyframes.len().checked_sub(1);
+
+                if let Some(index) = selected.or(last) {
+                    self.scene.camera_path.remove(index);
+                }
+            }
+            Action::TogglePathPlayback => {
+                self.path_distance = match self.path_distance {
+                    Some(_) => None,
+                    None => {
+                        info!(
+                            "Flying along camera path, {:.1} seconds per pass",
+                            self.scene.camera_path.duration()
+                        );
+                        Some(0.0)
+                    }
+                }
+            }
+            Action::TogglePathLoop => {
+                let looping = !self.scene.camera_path.looping;
+                self.scene.camera_path.set_looping(looping);
+            }
+            Action::SavePath => match self.scene.camera_path.save(CAMERA_PATH_PATH) {
+                Ok(()) => println!("Saved camera path to {}", CAMERA_PATH_PATH),
+                Err(e) => eprintln!("Error saving camera path: {:#?}", e),
+            },
+            Action::LoadPath => match CameraPath::load(CAMERA_PATH_PATH) {
+                Ok(path) => {
+                    self.scene.camera_path = path;
+                    self.scene.selected_keyframe = None;
+                }
+                Err(e) => eprintln!("Error loading camera path: {:#?}", e),
+            },
+
+            action => {
+                let mut context = Context {
+                    scene: &mut self.scene,
+                    bindings: &self.bindings,
+                    pending_maps: &mut self.pending_maps,
+                    layout_path: &self.layout_path,
+                };
+
+                for window in self.windows.values_mut() {
+                    if window.target() == target {
+                        window.handle_action(action, &mut context);
+                    }
+                }
+            }
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        match self.recorder.take() {
+            Some(recorder) => {
+                let recording = recorder.finish(self.scene.snapshot());
+
+                match recording.save(RECORDING_PATH) {
+                    Ok(()) => println!("Saved recording to {}", RECORDING_PATH),
+                    Err(e) => eprintln!("Error saving recording: {:#?}", e),
+                }
+            }
+            None => {
+                info!("Recording input");
+                self.recorder = Some(Recorder::new(self.scene.snapshot(), self.recorded_state()));
+            }
+        }
+    }
+
+    fn start_replay(&mut self) {
+        let recording = match Recording::load(RECORDING_PATH) {
+            Ok(recording) => recording,
+            Err(e) => {
+                eprintln!("Error loading recording: {:#?}", e);
+                return;
+            }
+        };
+
+        // Replaying while recording would record the replay's own input twice
+        if self.recorder.is_some() {
+            self.toggle_recording();
+        }
+
+        let (replay, start, state) = Replay::new(recording);
+        self.scene.restore(start);
+        self.restore_recorded_state(state);
+        for window in self.windows.values_mut() {
+            window.clear_input();
+        }
+        self.input.clear();
+        self.focused = None;
+        self.replay = Some(replay);
+        self.scheduler.request_frame();
+    }
+
+    /// Stop replaying. If the replay ran to the end, check that we ended up
+    /// in the same state as when the recording was made.
+    fn stop_replay(&mut self, finished: bool) {
+        if let Some(replay) = self.replay.take() {
+            if !finished {
+                println!("Replay stopped");
+            } else if self.scene.snapshot() == replay.end {
+                println!("Replay finished, state matches the recording");
+            } else {
+                eprintln!(
+                    "Replay diverged from the recording.\nExpected: {:#?}\nGot: {:#?}",
+                    replay.end,
+                    self.scene.snapshot()
+                );
+            }
+        }
+    }
+
+    /// Simulate a frame, if one is due.
+    /// Returns the globals to upload before drawing it.
+    pub fn frame(&mut self, now: Instant) -> Option<GlobalsUniform> {
+        if !self.scheduler.frame_due(now) {
+            return None;
+        }
+
+        // Everything since the last frame was simulated counts towards that one
+        self.profiler.end_frame();
+
+        let start = Instant::now();
+        let globals = self.simulate(now);
+        self.update_ui();
+        self.profiler.record(Phase::Update, start);
+
+        Some(globals)
+    }
+
+    fn simulate(&mut self, now: Instant) -> GlobalsUniform {
+        let mut globals = self.scheduler.tick(now);
+
+        if let Some(replay) = &mut self.replay {
+            match replay.next_frame() {
+                Some((inputs, delta)) => {
+                    for input in inputs {
+                        match input {
+                            Replayed::Input(target, event) => {
+                                let event = event.event(self.target_size(target));
+                                self.handle_input(target, event)
+                            }
+                            Replayed::Edit(scene) => self.scene.restore(scene),
+                        }
+                    }
+
+                    match delta {
+                        Some(delta) => globals.delta = delta,
+                        None => {
+                            // Trailing inputs without a frame, nothing more to simulate
+                            self.stop_replay(true);
+                            return globals;
+                        }
+                    }
+                }
+                None => {
+                    self.stop_replay(true);
+                    return globals;
+                }
+            }
+
+            // Keep going until the replay runs out
+            self.scheduler.request_frame();
+        }
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.frame(globals.delta);
+        }
+
+        if self.scene.update(
+            &self.input,
+            &self.bindings,
+            self.focused.map(scope),
+            globals.delta,
+        ) || self.input.any_held()
+        {
+            self.scheduler.request_frame();
+        }
+
+        if let Some(distance) = self.path_distance {
+            let path = &self.scene.camera_path;
+            let distance = distance + path.speed * globals.delta;
+            let past_end = !path.looping && distance >= path.length();
+
+            match path.sample(distance) {
+                Some(keyframe) if !past_end => {
+                    self.scene.fly_to(&keyframe);
+                    self.path_distance = Some(distance);
+                }
+                Some(keyframe) => {
+                    self.scene.fly_to(&keyframe);
+                    self.path_distance = None;
+                }
+                None => self.path_distance = None,
+            }
+
+            self.scheduler.request_frame();
+        }
+
+        globals
+    }
+
+    /// Lay out every window's user interface, after simulating so it shows the frame's scene.
+    /// Edits made there are recorded as the scene they leave behind.
+    fn update_ui(&mut self) {
+        let before = self
+            .recorder
+            .as_ref()
+            .filter(|_| self.show_inspector)
+            .map(|_| self.scene.snapshot());
+
+        let mut context = Context {
+            scene: &mut self.scene,
+            bindings: &self.bindings,
+            pending_maps: &mut self.pending_maps,
+            layout_path: &self.layout_path,
+        };
+
+        let mut again = false;
+        for window in self.windows.values_mut() {
+            again |= window.update_ui(&mut context);
+        }
+
+        if again {
+            self.scheduler.request_frame();
+        }
+
+        if let (Some(recorder), Some(before)) = (&mut self.recorder, before) {
+            let after = self.scene.snapshot();
+            if after != before {
+                recorder.edit(after);
+            }
+        }
+    }
+
+    /// Save the screenshot asked for, if any.
+    /// Call after uploading the frame's globals.
+    pub fn take_screenshot(&mut self, context: DeviceContext) {
+        let window = match self.pending_screenshot.take() {
+            Some(window_id) => match self.windows.get_mut(&window_id) {
+                Some(window) => window,
+                None => return,
+            },
+            None => return,
+        };
+
+        match screenshot::capture(window.as_mut(), &self.scene, &self.screenshot_dir, context) {
+            Ok(path) => println!("Saved screenshot to {}", path.display()),
+            Err(e) => eprintln!("Error saving screenshot: {:#?}", e),
+        }
+    }
+
+    /// Apply the surface change asked for, if any.
+    pub fn change_surface(&mut self, device: &Device, pipelines: &PipelineCache) {
+        let (window_id, change) = match self.pending_surface_change.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let window = match self.windows.get_mut(&window_id) {
+            Some(window) => window,
+            None => return,
+        };
+        let target = window.target();
+
+        match change {
+            SurfaceChange::CyclePresentMode => {
+                if let Some(viewport) = window.viewport_mut() {
+                    let present_mode = viewport.next_present_mode();
+                    viewport.set_present_mode(device, present_mode);
+                    println!("{:?} window present mode: {:?}", target, present_mode);
+                }
+            }
+            SurfaceChange::CycleFormat => {
+                let format = match window.viewport() {
+                    Some(viewport) => viewport.next_format(),
+                    None => return,
+                };
+
+                if let Err(e) = window.set_texture_format(device, pipelines, format) {
+                    eprintln!("Error changing {:?} window format: {:#?}", target, e);
+                    return;
+                }
+                if let Some(viewport) = window.viewport_mut() {
+                    viewport.set_format(device, format);
+                }
+                println!("{:?} window surface format: {:?}", target, format);
+            }
+        }
+
+        self.scheduler.request_frame();
+    }
+
+    /// Show the frame timings in the window titles while the profiler is,
+    /// and put the titles back once it isn't.
+    pub fn update_titles(&mut self, now: Instant) {
+        let summary = match (self.profiler.enabled, self.titles_updated) {
+            (true, Some(updated)) if now - updated < TITLE_INTERVAL => return,
+            (true, _) => {
+                self.titles_updated = Some(now);
+                Some(self.profiler.summary())
+            }
+            (false, Some(_)) => {
+                self.titles_updated = None;
+                None
+            }
+            (false, None) => return,
+        };
+
+        for window in self.windows.values() {
+            if let Some(viewport) = window.viewport() {
+                let title = app_window::title(window.target());
+
+                viewport.window.set_title(&match &summary {
+                    Some(summary) => format!("{} | {}", title, summary),
+                    None => title,
+                });
+            }
+        }
+    }
+
+    /// The key bindings a window shows, if help is shown.
+    fn help_lines(&self, target: Target) -> Option<Vec<String>> {
+        self.show_help
+            .then(|| self.bindings.help_lines(&[Scope::Global, scope(target)]))
+    }
+
+    fn update_help(&mut self) {
+        let targets = self
+            .windows
+            .iter()
+            .map(|(window_id, window)| (*window_id, window.target()))
+            .collect::<Vec<_>>();
+
+        for (window_id, target) in targets {
+            let lines = self.help_lines(target);
+            if let Some(window) = self.windows.get_mut(&window_id) {
+                window.set_help(lines);
+            }
+        }
+
+        self.scheduler.request_frame();
+    }
+}
+
+fn scope(target: Target) -> Scope {
+    match target {
+        Target::Main => Scope::Main,
+        Target::Extra => Scope::Extra,
+        Target::Profile => Scope::Profile,
+        Target::Device => Scope::Global,
+    }
+}
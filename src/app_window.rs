@@ -0,0 +1,202 @@
+use std::{path::Path, time::Instant};
+
+use color_eyre::{eyre::ContextCompat, Result};
+use log::debug;
+use wgpu::{
+    CommandBuffer, Device, SurfaceError, TextureFormat, TextureView, TextureViewDescriptor,
+};
+use winit::{dpi::PhysicalSize, event::Event, window::WindowId};
+
+use crate::{
+    bindings::{Action, Bindings},
+    gpu::DeviceContext,
+    pipeline::PipelineCache,
+    profiler::{GpuTimer, Phase, Profiler},
+    recording::{InputEvent, Target},
+    scene::Scene,
+    session::Maps,
+    viewport::Viewport,
+};
+
+/// The title a kind of window starts out with.
+pub fn title(target: Target) -> String {
+    format!("wgpu-explorer: {:?} window", target)
+}
+
+/// What windows get to see and change while handling input.
+pub struct Context<'a> {
+    pub scene: &'a mut Scene,
+    pub bindings: &'a Bindings,
+
+    /// Maps to rebuild every window from, before the next frame
+    #[cfg_attr(not(feature = "inspector"), allow(dead_code))]
+    pub pending_maps: &'a mut Option<Maps>,
+
+    /// Where the square's mesh is saved to and loaded from
+    pub layout_path: &'a Path,
+}
+
+/// A window showing some view of the [`Scene`].
+///
+/// Windows don't own any shared state, so they can be opened and closed
+/// independently of each other.
+pub trait AppWindow {
+    /// Which kind of window this is, deciding its key binding scope
+    /// and where recorded input is replayed to.
+    fn target(&self) -> Target;
+
+    /// `None` for headless windows.
+    fn viewport(&self) -> Option<&Viewport>;
+
+    fn viewport_mut(&mut self) -> Option<&mut Viewport>;
+
+    /// Give up the window to rebuild it, e.g. on a new device.
+    fn into_viewport(self: Box<Self>) -> Option<Viewport>;
+
+    /// Inner size in physical pixels, also for headless windows.
+    fn size(&self) -> PhysicalSize<u32>;
+
+    /// Physical pixels per logical pixel. Headless windows are drawn at 1.0.
+    fn scale_factor(&self) -> f64 {
+        self.viewport()
+            .map_or(1.0, |viewport| viewport.window.scale_factor())
+    }
+
+    fn texture_format(&self) -> TextureFormat;
+
+    /// Rebuild whatever depends on the format drawn into.
+    /// The viewport's surface is left to the caller.
+    fn set_texture_format(
+        &mut self,
+        device: &Device,
+        pipelines: &PipelineCache,
+        format: TextureFormat,
+    ) -> Result<()>;
+
+    /// Also called when the scale factor changes,
+    /// by which time [`AppWindow::scale_factor`] returns the new one.
+    fn resize(&mut self, device: &Device, size: PhysicalSize<u32>);
+
+    /// Input other than key presses, which are turned into actions.
+    /// Returns true if something changed which should be redrawn.
+    fn handle_input(&mut self, event: InputEvent, context: &mut Context) -> bool;
+
+    /// Actions bound in this window's scope.
+    fn handle_action(&mut self, _action: Action, _context: &mut Context) {}
+
+    /// Key bindings to show over the window, `None` to hide them.
+    fn set_help(&mut self, lines: Option<Vec<String>>);
+
+    /// Forget any input in progress, e.g. a drag or a grabbed cursor,
+    /// when the scene is replaced or the window loses focus.
+    fn clear_input(&mut self) {}
+
+    /// Show or hide the window's inspector panel, if it has one.
+    fn set_inspector(&mut self, _shown: bool) {}
+
+    /// Offer a window event to the window's own user interface before it becomes input.
+    /// Returns true if the interface takes it.
+    fn capture_event(&mut self, _event: &Event<()>) -> bool {
+        false
+    }
+
+    /// Lay out the window's own user interface, which may edit the scene.
+    /// Returns true if it wants another frame.
+    fn update_ui(&mut self, _context: &mut Context) -> bool {
+        false
+    }
+
+    /// Record drawing into any texture view of the window's size and texture format.
+    /// Passes are timed with `timer` if given.
+    fn encode(
+        &mut self,
+        context: DeviceContext,
+        scene: &Scene,
+        texture_view: &TextureView,
+        timer: Option<&GpuTimer>,
+    ) -> CommandBuffer;
+
+    /// Draw into any texture view of the window's size and texture format.
+    fn render_to(&mut self, context: DeviceContext, scene: &Scene, texture_view: &TextureView) {
+        let commands = self.encode(context, scene, texture_view, None);
+        context.queue.submit(Some(commands));
+    }
+
+    /// Pick up changes to files on disk, such as shaders.
+    fn reload(&mut self, _device: &Device, _pipelines: &PipelineCache) -> Result<()> {
+        Ok(())
+    }
+
+    fn window_id(&self) -> Option<WindowId> {
+        self.viewport().map(|viewport| viewport.window.id())
+    }
+
+    fn request_redraw(&self) {
+        if let Some(viewport) = self.viewport() {
+            viewport.window.request_redraw();
+        }
+    }
+
+    /// Draw into the window, timing each phase of it.
+    fn render(
+        &mut self,
+        context: DeviceContext,
+        scene: &Scene,
+        profiler: &mut Profiler,
+        timer: Option<&GpuTimer>,
+    ) -> Result<()> {
+        let viewport = self
+            .viewport()
+            .wrap_err_with(|| format!("{:?} window is headless", self.target()))?;
+
+        if viewport.is_suspended() {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        let frame = viewport.surface.get_current_frame();
+        profiler.record(Phase::Acquire, start);
+
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+                debug!(
+                    "{:?} window surface lost or outdated, reconfiguring",
+                    self.target()
+                );
+                viewport.configure(context.device);
+                viewport.window.request_redraw();
+                return Ok(());
+            }
+            Err(SurfaceError::Timeout) => {
+                debug!(
+                    "{:?} window surface timed out, skipping frame",
+                    self.target()
+                );
+                viewport.window.request_redraw();
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let texture_view = frame
+            .output
+            .texture
+            .create_view(&TextureViewDescriptor::default());
+
+        let start = Instant::now();
+        let commands = self.encode(context, scene, &texture_view, timer);
+        profiler.record(Phase::Encode, start);
+
+        let start = Instant::now();
+        context.queue.submit(Some(commands));
+        profiler.record(Phase::Submit, start);
+
+        // Presented when dropped
+        let start = Instant::now();
+        drop(texture_view);
+        drop(frame);
+        profiler.record(Phase::Present, start);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+use std::{collections::HashMap, fmt, path::Path, str::FromStr};
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use serde::{
+    de::{value::StrDeserializer, IntoDeserializer},
+    Deserialize,
+};
+use winit::event::VirtualKeyCode;
+
+use crate::input::InputState;
+
+/// Where a binding applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Global,
+    Main,
+    Extra,
+    Profile,
+}
+
+/// Everything a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+pub enum Action {
+    CycleFrameMode,
+    ToggleHelp,
+    ToggleRecording,
+    ToggleReplay,
+    Screenshot,
+    ReopenWindows,
+    CyclePresentMode,
+    CycleSurfaceFormat,
+    RebuildGpu,
+    ToggleProfiler,
+    SaveProfile,
+    PrintResources,
+
+    AddKeyframe,
+    RemoveKeyframe,
+    TogglePathPlayback,
+    TogglePathLoop,
+    SavePath,
+    LoadPath,
+
+    DisplaceAmountDown,
+    DisplaceAmountUp,
+    FovNarrower,
+    FovWider,
+
+    SelectVertex1,
+    SelectVertex2,
+    SelectVertex3,
+    SelectVertex4,
+
+    VertexLeft,
+    VertexRight,
+    VertexUp,
+    VertexDown,
+    ToggleVertexEditor,
+    ToggleSnap,
+    SaveSurface,
+    LoadSurface,
+
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    TurnLeft,
+    TurnRight,
+    ToggleCursorGrab,
+    ToggleInspector,
+
+    CameraHigher,
+    CameraLower,
+    ViewFarther,
+    ViewNearer,
+}
+
+impl Action {
+    /// A short human readable description, used by the help overlay.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::CycleFrameMode => "Cycle frame mode (on-demand, vsync, capped)",
+            Action::ToggleHelp => "Toggle this help",
+            Action::ToggleRecording => "Start/stop recording input to recording.ron",
+            Action::ToggleReplay => "Start/stop replaying recording.ron",
+            Action::Screenshot => "Save a screenshot of the focused window",
+            Action::ReopenWindows => "Reopen closed windows",
+            Action::CyclePresentMode => "Cycle the focused window's present mode (vsync)",
+            Action::CycleSurfaceFormat => {
+                "Cycle through the formats the focused window's surface offers"
+            }
+            Action::RebuildGpu => "Rebuild everything on a new device, as when it is lost",
+            Action::ToggleProfiler => "Show or hide frame timings in the window titles",
+            Action::SaveProfile => "Save frame timings to profile.csv",
+            Action::PrintResources => "Print the GPU buffers, textures and pipelines alive",
+            Action::AddKeyframe => "Add a camera path keyframe at the camera",
+            Action::RemoveKeyframe => "Remove the selected (or last) path keyframe",
+            Action::TogglePathPlayback => "Start/stop flying along the camera path",
+            Action::TogglePathLoop => "Toggle whether the camera path loops",
+            Action::SavePath => "Save the camera path to camera_path.ron",
+            Action::LoadPath => "Load the camera path from camera_path.ron",
+            Action::DisplaceAmountDown => "Decrease vertex step size",
+            Action::DisplaceAmountUp => "Increase vertex step size",
+            Action::FovNarrower => "Narrow the field of view",
+            Action::FovWider => "Widen the field of view",
+            Action::SelectVertex1 => "Select vertex 1",
+            Action::SelectVertex2 => "Select vertex 2",
+            Action::SelectVertex3 => "Select vertex 3",
+            Action::SelectVertex4 => "Select vertex 4",
+            Action::VertexLeft => "Move selected vertex left",
+            Action::VertexRight => "Move selected vertex right",
+            Action::VertexUp => "Move selected vertex up",
+            Action::VertexDown => "Move selected vertex down",
+            Action::ToggleVertexEditor => {
+                "Show vertex handles, drag them with the left mouse button"
+            }
+            Action::ToggleSnap => "Toggle snapping dragged vertices to the vertex step size",
+            Action::SaveSurface => "Save the square's mesh to the --layout file",
+            Action::LoadSurface => "Load the square's mesh from the --layout file",
+            Action::MoveLeft => "Move camera left",
+            Action::MoveRight => "Move camera right",
+            Action::MoveUp => "Move camera up",
+            Action::MoveDown => "Move camera down",
+            Action::TurnLeft => "Turn camera left",
+            Action::TurnRight => "Turn camera right",
+            Action::ToggleCursorGrab => "Grab the cursor for mouse look",
+            Action::ToggleInspector => "Show or hide the inspector panel for editing the scene",
+            Action::CameraHigher => "Fly the camera higher",
+            Action::CameraLower => "Fly the camera lower",
+            Action::ViewFarther => "See farther",
+            Action::ViewNearer => "See less far",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = serde::de::value::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let deserializer: StrDeserializer<Self::Err> = name.into_deserializer();
+        Action::deserialize(deserializer)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MouseSettings {
+    /// Radians per pixel of mouse movement
+    pub sensitivity: f32,
+
+    /// Moving the mouse up looks down
+    pub invert_y: bool,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.005,
+            invert_y: false,
+        }
+    }
+}
+
+/// The bindings file as written on disk: Per scope, which keys trigger an action.
+/// Actions are kept as strings since TOML keys can't be deserialized into enums directly.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingsFile {
+    #[serde(default)]
+    mouse: MouseSettings,
+
+    #[serde(default)]
+    global: HashMap<String, Vec<VirtualKeyCode>>,
+
+    #[serde(default)]
+    main: HashMap<String, Vec<VirtualKeyCode>>,
+
+    #[serde(default)]
+    extra: HashMap<String, Vec<VirtualKeyCode>>,
+
+    #[serde(default)]
+    profile: HashMap<String, Vec<VirtualKeyCode>>,
+}
+
+#[derive(Debug)]
+pub struct Bindings {
+    keys: HashMap<Scope, HashMap<VirtualKeyCode, Action>>,
+    pub mouse: MouseSettings,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::from_toml(include_str!("bindings.toml")).expect("Default bindings are valid")
+    }
+}
+
+impl Bindings {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let toml = std::fs::read_to_string(&path)?;
+
+        Self::from_toml(&toml)
+    }
+
+    fn from_toml(toml: &str) -> Result<Self> {
+        let file: BindingsFile = toml::from_str(toml)?;
+
+        let mut keys = HashMap::new();
+        let mut conflicts = vec![];
+
+        for (scope, actions) in [
+            (Scope::Global, file.global),
+            (Scope::Main, file.main),
+            (Scope::Extra, file.extra),
+            (Scope::Profile, file.profile),
+        ] {
+            let scope_keys: &mut HashMap<VirtualKeyCode, Action> = keys.entry(scope).or_default();
+
+            // Sorted, so conflicts are reported the same way every time
+            let mut actions = actions
+                .into_iter()
+                .map(|(name, keys)| {
+                    let action = name
+                        .parse::<Action>()
+                        .wrap_err_with(|| format!("Unknown action in {:?}", scope))?;
+                    Ok((action, keys))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            actions.sort_by_key(|(action, _)| *action);
+
+            for (action, action_keys) in actions {
+                for key in action_keys {
+                    if let Some(other) = scope_keys.insert(key, action) {
+                        conflicts.push(format!(
+                            "{:?} is bound to both {:?} and {:?} in {:?}",
+                            key, other, action, scope
+                        ));
+                    }
+                }
+            }
+        }
+
+        for scope in [Scope::Main, Scope::Extra, Scope::Profile] {
+            for (key, action) in &keys[&scope] {
+                if let Some(other) = keys[&Scope::Global].get(key) {
+                    conflicts.push(format!(
+                        "{:?} is bound to {:?} in {:?}, which hides global {:?}",
+                        key, action, scope, other
+                    ));
+                }
+            }
+        }
+
+        if conflicts.is_empty() {
+            Ok(Self {
+                keys,
+                mouse: file.mouse,
+            })
+        } else {
+            conflicts.sort();
+            Err(eyre!("Conflicting key bindings:\n{}", conflicts.join("\n")))
+        }
+    }
+
+    /// Which action a key triggers in the given scope, falling back to global bindings.
+    pub fn lookup(&self, scope: Scope, key: VirtualKeyCode) -> Option<Action> {
+        self.keys[&scope]
+            .get(&key)
+            .or_else(|| self.keys[&Scope::Global].get(&key))
+            .copied()
+    }
+
+    /// Whether any key bound to the action is held.
+    pub fn is_held(&self, input: &InputState, scope: Scope, action: Action) -> bool {
+        [scope, Scope::Global].iter().any(|scope| {
+            self.keys[scope]
+                .iter()
+                .any(|(key, bound)| *bound == action && input.is_held(*key))
+        })
+    }
+
+    /// -1.0, 0.0 or 1.0 depending on which of the two actions are held.
+    pub fn axis(
+        &self,
+        input: &InputState,
+        scope: Scope,
+        negative: Action,
+        positive: Action,
+    ) -> f32 {
+        let mut value = 0.0;
+
+        if self.is_held(input, scope, negative) {
+            value -= 1.0;
+        }
+        if self.is_held(input, scope, positive) {
+            value += 1.0;
+        }
+
+        value
+    }
+
+    /// One line per action, listing its keys. Grouped by scope, in the order given.
+    pub fn help_lines(&self, scopes: &[Scope]) -> Vec<String> {
+        let mut lines = vec![];
+
+        for scope in scopes {
+            let mut by_action: HashMap<Action, Vec<String>> = HashMap::new();
+            for (key, action) in &self.keys[scope] {
+                by_action
+                    .entry(*action)
+                    .or_default()
+                    .push(format!("{:?}", key));
+            }
+
+            if by_action.is_empty() {
+                continue;
+            }
+
+            let mut by_action = by_action.into_iter().collect::<Vec<_>>();
+            by_action.sort_by_key(|(action, _)| *action);
+
+            lines.push(format!("{}:", scope));
+            for (action, mut keys) in by_action {
+                keys.sort();
+                lines.push(format!(
+                    "  {:<20} {}",
+                    keys.join(", "),
+                    action.description()
+                ));
+            }
+        }
+
+        lines
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scope::Global => "Global",
+            Scope::Main => "Main window",
+            Scope::Extra => "Extra window",
+            Scope::Profile => "Profile window",
+        };
+
+        write!(f, "{}", name)
+    }
+}
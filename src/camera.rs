@@ -1,6 +1,48 @@
 use std::f32::consts;
 
-use crate::{misc, vec::Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::vec::Vec2;
+
+/// The field of view is kept within these bounds, radians.
+pub const FOV_MIN: f32 = consts::FRAC_PI_8;
+pub const FOV_MAX: f32 = consts::PI;
+
+/// How far we may look up or down, radians.
+pub const PITCH_MAX: f32 = consts::FRAC_PI_2 * 0.99;
+
+/// How high the camera may fly, in height map units where the highest terrain is 1.0.
+pub const HEIGHT_MAX: f32 = 2.0;
+
+/// Seeing less far than this leaves nothing to look at.
+pub const VIEWING_DISTANCE_MIN: f32 = 0.05;
+
+/// Below this speed the camera is considered to be standing still.
+const REST_SPEED: f32 = 1e-3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Motion {
+    // Units per second squared while a movement key is held
+    pub acceleration: f32,
+
+    // Radians per second squared while a turn key is held
+    pub angular_acceleration: f32,
+
+    // How quickly velocities decay, per second. Higher is snappier.
+    pub damping: f32,
+}
+
+impl Default for Motion {
+    fn default() -> Self {
+        Self {
+            acceleration: 6.0,
+            angular_acceleration: 6.0 * consts::PI,
+            damping: 6.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Camera {
     // [-1.0, 1.0], independent of terrain size
     pub x: f32,
@@ -8,7 +50,10 @@ pub struct Camera {
     // [-1.0, 1.0], independent of terrain size
     pub y: f32,
 
-    // How far our vision goes, [0.0, 1.0].
+    // Height map units, [0.0, HEIGHT_MAX]
+    pub height: f32,
+
+    // How far our vision goes, [VIEWING_DISTANCE_MIN, 1.0].
     pub viewing_distance: f32,
 
     // Radians, (-pi, pi]
@@ -19,6 +64,17 @@ pub struct Camera {
 
     // Radians: Angle centered on viewing angle.
     pub fov: f32,
+
+    // Radians, up is positive, [-PITCH_MAX, PITCH_MAX]
+    pub pitch: f32,
+
+    // Units per second, (x, y)
+    pub velocity: [f32; 2],
+
+    // Radians per second
+    pub angular_velocity: f32,
+
+    pub motion: Motion,
 }
 
 impl Default for Camera {
@@ -27,6 +83,7 @@ impl Default for Camera {
             x: 0.0,
             y: 0.0,
 
+            height: 0.6,
             viewing_distance: 0.25,
 
             // Straight "up"
@@ -34,6 +91,11 @@ impl Default for Camera {
             viewing_dir: Vec2::new_from_angle(consts::FRAC_PI_2),
 
             fov: consts::FRAC_PI_4,
+            pitch: 0.0,
+
+            velocity: [0.0, 0.0],
+            angular_velocity: 0.0,
+            motion: Motion::default(),
         }
     }
 }
@@ -44,11 +106,54 @@ impl Camera {
         self.viewing_dir = Vec2::new_from_angle(self.viewing_angle);
     }
 
-    pub fn displace(&mut self, direction: misc::Direction, amount: f32) {
-        let (x, y) = misc::displace(direction, amount);
+    pub fn look_up(&mut self, angle: f32) {
+        self.pitch = (self.pitch + angle).clamp(-PITCH_MAX, PITCH_MAX);
+    }
+
+    /// Fly `amount` higher, staying within [0, HEIGHT_MAX].
+    pub fn raise(&mut self, amount: f32) {
+        self.height = (self.height + amount).clamp(0.0, HEIGHT_MAX);
+    }
+
+    /// See `amount` further, staying within [VIEWING_DISTANCE_MIN, 1.0].
+    pub fn look_further(&mut self, amount: f32) {
+        self.viewing_distance = (self.viewing_distance + amount).clamp(VIEWING_DISTANCE_MIN, 1.0);
+    }
+
+    /// Change the field of view by `angle` radians, staying within [FOV_MIN, FOV_MAX].
+    pub fn zoom(&mut self, angle: f32) {
+        self.fov = (self.fov + angle).clamp(FOV_MIN, FOV_MAX);
+    }
+
+    /// Integrate the camera's motion over `dt` seconds.
+    ///
+    /// `thrust` is the wanted movement direction (x, y) and `turn` the wanted
+    /// rotation direction, each component in [-1, 1].
+    pub fn update(&mut self, thrust: (f32, f32), turn: f32, dt: f32) {
+        let decay = (-self.motion.damping * dt).exp();
+
+        self.velocity[0] = (self.velocity[0] + thrust.0 * self.motion.acceleration * dt) * decay;
+        self.velocity[1] = (self.velocity[1] + thrust.1 * self.motion.acceleration * dt) * decay;
+        self.angular_velocity =
+            (self.angular_velocity + turn * self.motion.angular_acceleration * dt) * decay;
+
+        if thrust == (0.0, 0.0) && self.velocity[0].hypot(self.velocity[1]) < REST_SPEED {
+            self.velocity = [0.0, 0.0];
+        }
+        if turn == 0.0 && self.angular_velocity.abs() < REST_SPEED {
+            self.angular_velocity = 0.0;
+        }
+
+        self.x += self.velocity[0] * dt;
+        self.y += self.velocity[1] * dt;
+
+        if self.angular_velocity != 0.0 {
+            self.rotate(self.angular_velocity * dt);
+        }
+    }
 
-        self.x += x;
-        self.y += y;
+    pub fn is_moving(&self) -> bool {
+        self.velocity != [0.0, 0.0] || self.angular_velocity != 0.0
     }
 
     // pub fn within_view(&self, x: f32, y: f32) -> bool {
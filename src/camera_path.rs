@@ -0,0 +1,310 @@
+use std::{f32::consts, fs, path::Path};
+
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{Camera, HEIGHT_MAX};
+
+/// Bumped whenever the file layout changes in an incompatible way.
+const VERSION: u32 = 2;
+
+/// Samples per spline segment in the arc length table.
+const SAMPLES_PER_SEGMENT: usize = 32;
+
+/// A camera pose along a path.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub x: f32,
+    pub y: f32,
+    pub viewing_angle: f32,
+    pub fov: f32,
+    pub pitch: f32,
+    pub height: f32,
+}
+
+impl Keyframe {
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            x: camera.x,
+            y: camera.y,
+            viewing_angle: camera.viewing_angle,
+            fov: camera.fov,
+            pitch: camera.pitch,
+            height: camera.height,
+        }
+    }
+
+    /// Move the camera to this pose, leaving it at rest.
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.x = self.x;
+        camera.y = self.y;
+        camera.fov = self.fov;
+        camera.pitch = self.pitch;
+        // The spline may overshoot between keyframes
+        camera.height = self.height.clamp(0.0, HEIGHT_MAX);
+        camera.rotate(self.viewing_angle - camera.viewing_angle);
+
+        camera.velocity = [0.0, 0.0];
+        camera.angular_velocity = 0.0;
+    }
+}
+
+/// Catmull-Rom interpolation between `p1` and `p2`, `t` in [0, 1].
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    0.5 * ((2.0 * p1)
+        + (-p0 + p2) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (-p0 + 3.0 * p1 - 3.0 * p2 + p3) * t3)
+}
+
+/// Keyframes joined by a Catmull-Rom spline, traversed at constant speed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraPath {
+    pub version: u32,
+
+    pub keyframes: Vec<Keyframe>,
+
+    /// Whether the end joins back up with the start
+    pub looping: bool,
+
+    /// Units per second along the path
+    pub speed: f32,
+
+    /// Keyframe angles without jumps between -pi and pi
+    #[serde(skip)]
+    angles: Vec<f32>,
+
+    /// How much the angle changes over one lap of a looping path
+    #[serde(skip)]
+    lap_turn: f32,
+
+    /// Cumulative path length at each sample, see [`SAMPLES_PER_SEGMENT`]
+    #[serde(skip)]
+    lengths: Vec<f32>,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            keyframes: vec![],
+            looping: true,
+            speed: 0.25,
+            angles: vec![],
+            lap_turn: 0.0,
+            lengths: vec![],
+        }
+    }
+}
+
+impl CameraPath {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut camera_path: Self = ron::de::from_str(&fs::read_to_string(path)?)?;
+
+        if camera_path.version != VERSION {
+            return Err(eyre!(
+                "Camera path has version {}, expected {}",
+                camera_path.version,
+                VERSION
+            ));
+        }
+
+        // Anything else would never get anywhere, or fly backwards
+        if !camera_path.speed.is_finite() || camera_path.speed <= 0.0 {
+            return Err(eyre!(
+                "Camera path speed must be above 0, got {}",
+                camera_path.speed
+            ));
+        }
+
+        camera_path.rebuild();
+        Ok(camera_path)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let ron = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, ron)?;
+
+        Ok(())
+    }
+
+    pub fn push(&mut self, keyframe: Keyframe) {
+        self.keyframes.push(keyframe);
+        self.rebuild();
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.keyframes.remove(index);
+        self.rebuild();
+    }
+
+    pub fn move_keyframe(&mut self, index: usize, x: f32, y: f32) {
+        self.keyframes[index].x = x.clamp(-1.0, 1.0);
+        self.keyframes[index].y = y.clamp(-1.0, 1.0);
+        self.rebuild();
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+        self.rebuild();
+    }
+
+    fn segments(&self) -> usize {
+        match self.keyframes.len() {
+            0 | 1 => 0,
+            n if self.looping => n,
+            n => n - 1,
+        }
+    }
+
+    /// Keyframe `index`, wrapping around for looping paths and clamping otherwise.
+    /// Angles are unwrapped so the spline turns the short way between neighbours.
+    fn keyframe(&self, index: isize) -> Keyframe {
+        let n = self.keyframes.len() as isize;
+
+        let (wrapped, laps) = if self.looping {
+            (index.rem_euclid(n), index.div_euclid(n))
+        } else {
+            (index.clamp(0, n - 1), 0)
+        };
+
+        let mut keyframe = self.keyframes[wrapped as usize];
+        keyframe.viewing_angle = self.angles[wrapped as usize] + laps as f32 * self.lap_turn;
+
+        keyframe
+    }
+
+    /// The pose `t` in [0, 1] along segment `segment`, i.e. between keyframes
+    /// `segment` and `segment + 1`.
+    fn sample_segment(&self, segment: usize, t: f32) -> Keyframe {
+        let i = segment as isize;
+        let (p0, p1, p2, p3) = (
+            self.keyframe(i - 1),
+            self.keyframe(i),
+            self.keyframe(i + 1),
+            self.keyframe(i + 2),
+        );
+
+        let spline = |f: fn(&Keyframe) -> f32| catmull_rom(f(&p0), f(&p1), f(&p2), f(&p3), t);
+
+        Keyframe {
+            x: spline(|k| k.x),
+            y: spline(|k| k.y),
+            viewing_angle: spline(|k| k.viewing_angle),
+            fov: spline(|k| k.fov),
+            pitch: spline(|k| k.pitch),
+            height: spline(|k| k.height),
+        }
+    }
+
+    /// Recompute the unwrapped angles and arc length table after the keyframes changed,
+    /// or after deserializing without them.
+    pub fn rebuild(&mut self) {
+        self.angles.clear();
+        self.lengths.clear();
+
+        for (i, keyframe) in self.keyframes.iter().enumerate() {
+            let angle = match i {
+                0 => keyframe.viewing_angle,
+                _ => {
+                    let previous = self.keyframes[i - 1].viewing_angle;
+                    self.angles[i - 1] + shortest_turn(previous, keyframe.viewing_angle)
+                }
+            };
+            self.angles.push(angle);
+        }
+
+        if let (Some(first), Some(last)) = (self.keyframes.first(), self.keyframes.last()) {
+            let last_angle = self.angles[self.angles.len() - 1];
+            self.lap_turn = last_angle + shortest_turn(last.viewing_angle, first.viewing_angle)
+                - self.angles[0];
+        }
+
+        let segments = self.segments();
+        if segments == 0 {
+            return;
+        }
+
+        let mut length = 0.0;
+        let mut previous = self.sample_segment(0, 0.0);
+        self.lengths.push(0.0);
+
+        for segment in 0..segments {
+            for sample in 1..=SAMPLES_PER_SEGMENT {
+                let t = sample as f32 / SAMPLES_PER_SEGMENT as f32;
+                let current = self.sample_segment(segment, t);
+
+                length += (current.x - previous.x).hypot(current.y - previous.y);
+                self.lengths.push(length);
+
+                previous = current;
+            }
+        }
+    }
+
+    /// Total length of the path.
+    pub fn length(&self) -> f32 {
+        self.lengths.last().copied().unwrap_or(0.0)
+    }
+
+    /// How long one pass along the path takes at the path's speed, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.length() / self.speed
+    }
+
+    /// The pose `distance` units along the path.
+    /// Wraps for looping paths, clamps otherwise.
+    pub fn sample(&self, distance: f32) -> Option<Keyframe> {
+        match self.keyframes.len() {
+            0 => return None,
+            1 => return Some(self.keyframes[0]),
+            _ => {}
+        }
+
+        let length = self.length();
+        if length <= f32::EPSILON {
+            // Nowhere to go, e.g. all keyframes in one spot
+            return Some(self.keyframes[0]);
+        }
+
+        let distance = if self.looping {
+            distance.rem_euclid(length)
+        } else {
+            distance.clamp(0.0, length)
+        };
+
+        // The first sample at or beyond the distance
+        let upper = self
+            .lengths
+            .partition_point(|&l| l < distance)
+            .clamp(1, self.lengths.len() - 1);
+        let lower = upper - 1;
+
+        let span = self.lengths[upper] - self.lengths[lower];
+        let within = if span > 0.0 {
+            (distance - self.lengths[lower]) / span
+        } else {
+            0.0
+        };
+
+        // Sample indices to spline parameters
+        let to_segment = |sample: usize| {
+            let segment = (sample / SAMPLES_PER_SEGMENT).min(self.segments() - 1);
+            let t = (sample - segment * SAMPLES_PER_SEGMENT) as f32 / SAMPLES_PER_SEGMENT as f32;
+            (segment, t)
+        };
+
+        let (segment, t) = to_segment(lower);
+        let t = t + within / SAMPLES_PER_SEGMENT as f32;
+
+        Some(self.sample_segment(segment, t))
+    }
+}
+
+/// The signed angle to turn from `a` to `b` the short way around.
+fn shortest_turn(a: f32, b: f32) -> f32 {
+    (b - a + consts::PI).rem_euclid(consts::TAU) - consts::PI
+}
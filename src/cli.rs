@@ -0,0 +1,422 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, ValueEnum};
+use log::LevelFilter;
+use wgpu::TextureFormat;
+use winit::dpi::{LogicalSize, PhysicalPosition};
+
+use crate::{
+    camera::Camera,
+    camera_path::Keyframe,
+    export::ExportFormat,
+    mesh::Mesh,
+    session::Session,
+    square::{self, Square},
+    viewport::FormatRequest,
+};
+
+/// Explore height maps with wgpu.
+///
+/// The main window shows the radar over the camera's surroundings,
+/// the extra window the diffuse and height maps with the camera and its path,
+/// the profile window the terrain along the line of sight, side on.
+/// Press H in any of the windows for key bindings.
+///
+/// Window geometry, maps and the camera are restored from the previous session
+/// unless given here, and saved again on exit.
+#[derive(Debug, Parser)]
+#[clap(version)]
+pub struct Cli {
+    #[clap(flatten)]
+    pub maps: MapArgs,
+
+    /// Initial camera pose as x,y,angle,fov,pitch,height. Trailing values may be left out.
+    ///
+    /// x and y are in [-1, 1], the angles in radians and height in height map units.
+    #[clap(long, value_name = "POSE", value_parser = parse_camera, allow_hyphen_values = true)]
+    pub camera: Option<Keyframe>,
+
+    /// Mesh the main window draws on instead of the square: quad, grid:<COLUMNS>x<ROWS>,
+    /// circle:<SEGMENTS>, outline:<SEGMENTS> for the circle's rim as a line strip,
+    /// a .ron layout saved from the vertex editor or an OBJ file with faces or lines.
+    ///
+    /// The built-in meshes cover the square's area, OBJ positions are taken as NDC.
+    #[clap(long, value_name = "MESH", value_parser = parse_surface)]
+    pub surface: Option<Mesh>,
+
+    #[clap(flatten)]
+    pub windows: WindowArgs,
+
+    #[clap(flatten)]
+    pub graphics: Graphics,
+
+    #[clap(flatten)]
+    pub headless: Headless,
+
+    /// Where screenshots are saved
+    #[clap(
+        long,
+        value_name = "DIR",
+        env = "WGPU_EXPLORER_SCREENSHOT_DIR",
+        default_value = "screenshots"
+    )]
+    pub screenshot_dir: PathBuf,
+
+    /// Where the vertex editor saves the square's mesh to and loads it from
+    #[clap(long, value_name = "PATH", default_value = "surface.ron")]
+    pub layout: PathBuf,
+
+    /// Key bindings, reloaded when the file changes. The defaults are used if it is missing.
+    #[clap(
+        long,
+        value_name = "PATH",
+        default_value = concat!(env!("CARGO_MANIFEST_DIR"), "/src/bindings.toml")
+    )]
+    pub bindings: PathBuf,
+
+    /// Where the session is restored from and saved to
+    #[clap(long, value_name = "PATH", default_value = "session.ron")]
+    pub session: PathBuf,
+
+    /// Start from the defaults and don't save the session on exit
+    #[clap(long)]
+    pub no_session: bool,
+
+    /// Log level, one of off, error, warn, info, debug or trace.
+    /// Overrides RUST_LOG.
+    #[clap(long, value_name = "LEVEL")]
+    pub log_level: Option<LevelFilter>,
+}
+
+#[derive(Debug, Args)]
+#[clap(next_help_heading = "MAPS")]
+pub struct MapArgs {
+    /// Shader drawing the main window, hot-reloaded on change [default: src/shaders/radar.wgsl]
+    #[clap(long, value_name = "WGSL")]
+    pub shader: Option<PathBuf>,
+
+    /// Colour map, shown on the left of the extra window [default: src/aztec-diffuse.png]
+    #[clap(long, value_name = "IMAGE")]
+    pub diffuse_map: Option<PathBuf>,
+
+    /// Height map, shown on the right of the extra window [default: src/aztec-height.png]
+    #[clap(long, value_name = "IMAGE")]
+    pub height_map: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+#[clap(next_help_heading = "WINDOWS")]
+pub struct WindowArgs {
+    /// Inner size of the main window in logical pixels [default: 600x600]
+    #[clap(long, value_name = "WxH", value_parser = parse_size)]
+    pub main_size: Option<LogicalSize<u32>>,
+
+    /// Position of the main window's top left corner [default: 0,300]
+    #[clap(long, value_name = "X,Y", value_parser = parse_position, allow_hyphen_values = true)]
+    pub main_position: Option<PhysicalPosition<i32>>,
+
+    /// Inner size of the extra window in logical pixels [default: 800x600]
+    #[clap(long, value_name = "WxH", value_parser = parse_size)]
+    pub extra_size: Option<LogicalSize<u32>>,
+
+    /// Position of the extra window's top left corner [default: 620,300]
+    #[clap(long, value_name = "X,Y", value_parser = parse_position, allow_hyphen_values = true)]
+    pub extra_position: Option<PhysicalPosition<i32>>,
+
+    /// Inner size of the profile window in logical pixels [default: 600x300]
+    #[clap(long, value_name = "WxH", value_parser = parse_size)]
+    pub profile_size: Option<LogicalSize<u32>>,
+
+    /// Position of the profile window's top left corner [default: 1440,300]
+    #[clap(long, value_name = "X,Y", value_parser = parse_position, allow_hyphen_values = true)]
+    pub profile_position: Option<PhysicalPosition<i32>>,
+}
+
+#[derive(Debug, Args)]
+#[clap(next_help_heading = "GRAPHICS")]
+pub struct Graphics {
+    /// Which graphics API to use
+    #[clap(long, value_enum, default_value = "primary")]
+    pub backend: Backend,
+
+    /// How frames are presented to the windows, until changed with V.
+    /// Fifo where a window's surface doesn't offer it.
+    #[clap(long, value_enum, default_value = "fifo")]
+    pub present_mode: PresentMode,
+
+    /// Format of the windows' surfaces, until changed with B.
+    /// The preferred one where a window's surface doesn't offer it.
+    #[clap(long, value_enum, default_value = "preferred")]
+    pub surface_format: SurfaceFormat,
+
+    /// Frames per second in the capped frame mode, cycled to with F5
+    #[clap(long, value_name = "FPS", default_value = "30", value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps_cap: u32,
+}
+
+#[derive(Debug, Args)]
+#[clap(next_help_heading = "HEADLESS")]
+pub struct Headless {
+    /// Render a single frame without opening windows, save it as a PNG and exit
+    #[clap(long, value_name = "OUT.PNG", conflicts_with = "export")]
+    pub headless: Option<PathBuf>,
+
+    /// Fly along a camera path at a fixed timestep without opening windows,
+    /// writing every frame, and exit.
+    ///
+    /// OUT ending in .gif makes an animated GIF, .y4m an uncompressed Y4M video,
+    /// and OUT without an extension a directory of numbered PNGs.
+    /// The same arguments always give the same files.
+    #[clap(long, value_name = "OUT", value_parser = parse_export)]
+    pub export: Option<PathBuf>,
+
+    /// Which window to render, at its --main-size, --extra-size or --profile-size
+    /// with one physical pixel per logical pixel
+    #[clap(long, value_enum, default_value = "main")]
+    pub window: HeadlessWindow,
+
+    /// Camera path to fly along when exporting
+    #[clap(long, value_name = "CAMERA_PATH.RON", requires = "export")]
+    pub path: Option<PathBuf>,
+
+    /// Frames per second when exporting
+    #[clap(long, default_value = "30", value_parser = clap::value_parser!(u32).range(1..), requires = "export")]
+    pub fps: u32,
+
+    /// Number of frames to export [default: one pass of the path]
+    #[clap(long, conflicts_with = "seconds", requires = "export", value_parser = clap::value_parser!(u32).range(1..))]
+    pub frames: Option<u32>,
+
+    /// Seconds to export, instead of --frames
+    #[clap(long, requires = "export", value_parser = parse_seconds)]
+    pub seconds: Option<f32>,
+}
+
+impl Cli {
+    /// Override the session with whatever was given on the command line.
+    pub fn apply(&self, session: &mut Session) {
+        let maps = &self.maps;
+        if let Some(shader) = &maps.shader {
+            session.maps.shader = shader.clone();
+        }
+        if let Some(diffuse_map) = &maps.diffuse_map {
+            session.maps.diffuse_map = diffuse_map.clone();
+        }
+        if let Some(height_map) = &maps.height_map {
+            session.maps.height_map = height_map.clone();
+        }
+
+        let windows = &self.windows;
+        if let Some(size) = windows.main_size {
+            session.main_window.size = size;
+        }
+        if let Some(position) = windows.main_position {
+            session.main_window.position = position;
+        }
+        if let Some(size) = windows.extra_size {
+            session.extra_window.size = size;
+        }
+        if let Some(position) = windows.extra_position {
+            session.extra_window.position = position;
+        }
+        if let Some(size) = windows.profile_size {
+            session.profile_window.size = size;
+        }
+        if let Some(position) = windows.profile_position {
+            session.profile_window.position = position;
+        }
+
+        if let Some(camera) = &self.camera {
+            camera.apply(&mut session.main.camera);
+        }
+        if let Some(surface) = &self.surface {
+            session.main.square = Square::new(surface.clone());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum HeadlessWindow {
+    Main,
+    Extra,
+    Profile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Backend {
+    /// Vulkan, Metal, DX12 or WebGPU, whichever the platform has
+    Primary,
+    /// OpenGL, DX11
+    Secondary,
+    Vulkan,
+    Metal,
+    Dx12,
+    Dx11,
+    Gl,
+}
+
+impl From<Backend> for wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Primary => wgpu::Backends::PRIMARY,
+            Backend::Secondary => wgpu::Backends::SECONDARY,
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Dx11 => wgpu::Backends::DX11,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PresentMode {
+    /// Wait for vertical blank, no tearing
+    Fifo,
+    /// Replace queued frames, no tearing
+    Mailbox,
+    /// Present right away, may tear
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(mode: PresentMode) -> Self {
+        match mode {
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SurfaceFormat {
+    /// Whatever the surface prefers, usually sRGB
+    Preferred,
+    /// Shader output is converted to sRGB
+    Srgb,
+    /// Shader output is shown as is
+    Linear,
+    Bgra8UnormSrgb,
+    Bgra8Unorm,
+    Rgba8UnormSrgb,
+    Rgba8Unorm,
+}
+
+impl From<SurfaceFormat> for FormatRequest {
+    fn from(format: SurfaceFormat) -> Self {
+        match format {
+            SurfaceFormat::Preferred => FormatRequest::Preferred,
+            SurfaceFormat::Srgb => FormatRequest::Srgb(true),
+            SurfaceFormat::Linear => FormatRequest::Srgb(false),
+            SurfaceFormat::Bgra8UnormSrgb => FormatRequest::Exact(TextureFormat::Bgra8UnormSrgb),
+            SurfaceFormat::Bgra8Unorm => FormatRequest::Exact(TextureFormat::Bgra8Unorm),
+            SurfaceFormat::Rgba8UnormSrgb => FormatRequest::Exact(TextureFormat::Rgba8UnormSrgb),
+            SurfaceFormat::Rgba8Unorm => FormatRequest::Exact(TextureFormat::Rgba8Unorm),
+        }
+    }
+}
+
+fn parse_size(value: &str) -> Result<LogicalSize<u32>, String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or_else(|| "expected <WIDTH>x<HEIGHT>".to_string())?;
+
+    let parse = |n: &str| n.trim().parse::<u32>().map_err(|e| e.to_string());
+    let size = LogicalSize::new(parse(width)?, parse(height)?);
+
+    if size.width == 0 || size.height == 0 {
+        return Err("size must not be empty".to_string());
+    }
+
+    Ok(size)
+}
+
+fn parse_position(value: &str) -> Result<PhysicalPosition<i32>, String> {
+    let (x, y) = value
+        .split_once(',')
+        .ok_or_else(|| "expected <X>,<Y>".to_string())?;
+
+    let parse = |n: &str| n.trim().parse::<i32>().map_err(|e| e.to_string());
+
+    Ok(PhysicalPosition::new(parse(x)?, parse(y)?))
+}
+
+/// An export path in one of the formats [`ExportFormat::from_path`] knows.
+fn parse_export(value: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(value);
+    ExportFormat::from_path(&path).map_err(|e| e.to_string())?;
+
+    Ok(path)
+}
+
+fn parse_seconds(value: &str) -> Result<f32, String> {
+    let seconds = value.trim().parse::<f32>().map_err(|e| e.to_string())?;
+
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return Err("seconds must be a finite number above 0".to_string());
+    }
+
+    Ok(seconds)
+}
+
+/// Comma separated values overriding the default camera in keyframe field order.
+fn parse_camera(value: &str) -> Result<Keyframe, String> {
+    let mut camera = Keyframe::from_camera(&Camera::default());
+
+    let mut fields = [
+        &mut camera.x,
+        &mut camera.y,
+        &mut camera.viewing_angle,
+        &mut camera.fov,
+        &mut camera.pitch,
+        &mut camera.height,
+    ];
+
+    let values = value.split(',').map(str::trim).collect::<Vec<_>>();
+    if values.len() > fields.len() {
+        return Err("expected at most x,y,angle,fov,pitch,height".to_string());
+    }
+
+    for (field, value) in fields.iter_mut().zip(values) {
+        **field = value.parse().map_err(|e| format!("{:?}: {}", value, e))?;
+    }
+
+    Ok(camera)
+}
+
+/// A built-in mesh over the default square's area, or one loaded from a layout or OBJ file.
+fn parse_surface(value: &str) -> Result<Mesh, String> {
+    let (top_left, bottom_right) = (
+        [-square::EXTENT, square::EXTENT],
+        [square::EXTENT, -square::EXTENT],
+    );
+    let parse = |n: &str| n.trim().parse::<u32>().map_err(|e| e.to_string());
+
+    let (kind, parameters) = value.split_once(':').unwrap_or((value, ""));
+    let mesh = match kind {
+        "quad" => Mesh::quad(top_left, bottom_right),
+        "grid" => {
+            let (columns, rows) = parameters
+                .split_once('x')
+                .ok_or_else(|| "expected grid:<COLUMNS>x<ROWS>".to_string())?;
+            Mesh::grid(top_left, bottom_right, parse(columns)?, parse(rows)?)
+        }
+        "circle" => Mesh::circle([0.0, 0.0], square::EXTENT, parse(parameters)?),
+        "outline" => {
+            // The circle's rim, closed by going back to where it started
+            let circle = Mesh::circle([0.0, 0.0], square::EXTENT, parse(parameters)?);
+            let rim = &circle.vertices()[1..];
+            let points = rim
+                .iter()
+                .chain(&rim[..1])
+                .map(|vertex| vertex.pos)
+                .collect::<Vec<_>>();
+            Mesh::line_strip(&points)
+        }
+        _ if value.ends_with(".ron") => Square::load(value).map_err(|e| format!("{:#}", e))?.mesh,
+        _ => Mesh::load_obj(value).map_err(|e| format!("{:#}", e))?,
+    };
+
+    Ok(mesh)
+}
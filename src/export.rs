@@ -0,0 +1,153 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbaImage,
+};
+
+/// GIF colour quantization speed, 1 (best) to 30 (fastest).
+const GIF_SPEED: i32 = 10;
+
+/// What exported frames are written as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// Numbered PNG files in a directory
+    PngSequence,
+    /// An animated GIF
+    Gif,
+    /// An uncompressed YUV4MPEG2 stream, 4:4:4 chroma
+    Y4m,
+}
+
+impl ExportFormat {
+    /// Decided by the output's extension: `.gif`, `.y4m`, or none for a directory of PNGs.
+    /// Other extensions are rejected rather than making a directory named like a file.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().map(|extension| extension.to_string_lossy()) {
+            None => Ok(ExportFormat::PngSequence),
+            Some(extension) if extension == "gif" => Ok(ExportFormat::Gif),
+            Some(extension) if extension == "y4m" => Ok(ExportFormat::Y4m),
+            Some(extension) => Err(eyre!(
+                "Can't export to .{} files, expected .gif, .y4m or a directory without an extension",
+                extension
+            )),
+        }
+    }
+}
+
+enum Writer {
+    PngSequence(PathBuf),
+    Gif(GifEncoder<BufWriter<File>>),
+    Y4m(BufWriter<File>),
+}
+
+/// Writes frames of a fixed size at a fixed rate, one at a time.
+pub struct Exporter {
+    writer: Writer,
+    fps: u32,
+    width: u32,
+    height: u32,
+    frames: u32,
+}
+
+impl Exporter {
+    pub fn new(path: &Path, fps: u32, width: u32, height: u32) -> Result<Self> {
+        let writer = match ExportFormat::from_path(path)? {
+            ExportFormat::PngSequence => {
+                fs::create_dir_all(path)?;
+                Writer::PngSequence(path.to_owned())
+            }
+            ExportFormat::Gif => {
+                let mut encoder =
+                    GifEncoder::new_with_speed(BufWriter::new(File::create(path)?), GIF_SPEED);
+                encoder.set_repeat(Repeat::Infinite)?;
+                Writer::Gif(encoder)
+            }
+            ExportFormat::Y4m => {
+                let mut file = BufWriter::new(File::create(path)?);
+                writeln!(
+                    file,
+                    "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                    width, height, fps
+                )?;
+                Writer::Y4m(file)
+            }
+        };
+
+        Ok(Self {
+            writer,
+            fps,
+            width,
+            height,
+            frames: 0,
+        })
+    }
+
+    pub fn push(&mut self, image: RgbaImage) -> Result<()> {
+        if image.dimensions() != (self.width, self.height) {
+            return Err(eyre!(
+                "Frame is {:?}, expected {:?}",
+                image.dimensions(),
+                (self.width, self.height)
+            ));
+        }
+
+        match &mut self.writer {
+            Writer::PngSequence(directory) => {
+                let path = directory.join(format!("frame_{:05}.png", self.frames));
+                image.save_with_format(path, image::ImageFormat::Png)?;
+            }
+            Writer::Gif(encoder) => {
+                let delay = Delay::from_numer_denom_ms(1000, self.fps);
+                encoder.encode_frame(Frame::from_parts(image, 0, 0, delay))?;
+            }
+            Writer::Y4m(file) => {
+                file.write_all(b"FRAME\n")?;
+                file.write_all(&to_yuv444(&image))?;
+            }
+        }
+
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Flush everything to disk. Returns how many frames were written.
+    pub fn finish(self) -> Result<u32> {
+        match self.writer {
+            Writer::PngSequence(_) => {}
+            // The trailer is written when the encoder is dropped
+            Writer::Gif(encoder) => drop(encoder),
+            Writer::Y4m(mut file) => file.flush()?,
+        }
+
+        Ok(self.frames)
+    }
+}
+
+/// Planar Y, U then V, BT.601 limited range, as Y4M's C444 expects.
+fn to_yuv444(image: &RgbaImage) -> Vec<u8> {
+    let pixels = image.pixels().count();
+    let mut planes = vec![0; pixels * 3];
+    let (y_plane, chroma) = planes.split_at_mut(pixels);
+    let (u_plane, v_plane) = chroma.split_at_mut(pixels);
+
+    for (i, pixel) in image.pixels().enumerate() {
+        let [r, g, b, _] = pixel.0;
+        let (r, g, b) = (r as f32, g as f32, b as f32);
+
+        let y = 16.0 + 0.257 * r + 0.504 * g + 0.098 * b;
+        let u = 128.0 - 0.148 * r - 0.291 * g + 0.439 * b;
+        let v = 128.0 + 0.439 * r - 0.368 * g - 0.071 * b;
+
+        y_plane[i] = y.round() as u8;
+        u_plane[i] = u.round() as u8;
+        v_plane[i] = v.round() as u8;
+    }
+
+    planes
+}
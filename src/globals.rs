@@ -0,0 +1,52 @@
+use std::mem;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    BindGroupLayoutEntry, BindingType, BufferAddress, BufferBindingType, BufferDescriptor,
+    BufferSize, BufferUsages, ShaderStages,
+};
+
+/// Per-frame values shared by every window.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Pod, Zeroable)]
+pub struct GlobalsUniform {
+    /// Seconds since the scheduler was started
+    pub time: f32,
+
+    /// Seconds since the previous frame
+    pub delta: f32,
+
+    /// Index of the current frame, starting at zero
+    pub frame: u32,
+
+    /// Unused, keeps the struct 16 byte aligned
+    pub _padding: u32,
+}
+
+impl GlobalsUniform {
+    pub const fn size(&self) -> BufferAddress {
+        mem::size_of::<GlobalsUniform>() as BufferAddress
+    }
+
+    pub const fn bind_group_layout_entry(&self, binding: u32) -> BindGroupLayoutEntry {
+        BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::all(),
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: BufferSize::new(self.size()),
+            },
+            count: None,
+        }
+    }
+
+    pub fn buffer_descriptor(&self) -> BufferDescriptor<'_> {
+        BufferDescriptor {
+            label: Some("Globals"),
+            size: self.size(),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }
+    }
+}
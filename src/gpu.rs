@@ -0,0 +1,273 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use color_eyre::{
+    eyre::{eyre, ContextCompat},
+    Result,
+};
+use log::error;
+use wgpu::*;
+use winit::{event_loop::EventLoopWindowTarget, window::Window};
+
+use crate::{
+    app::App,
+    app_window::AppWindow,
+    globals::GlobalsUniform,
+    pipeline::PipelineCache,
+    profiler::GpuTimer,
+    recording::Target,
+    resources::{self, Tracked},
+    session::{Maps, WindowGeometry},
+    viewport::{FormatRequest, Viewport},
+    window_extra::WindowExtra,
+    window_main::WindowMain,
+    window_profile::WindowProfile,
+};
+
+/// The device, and what every window is created with.
+pub struct Gpu {
+    pub instance: Instance,
+    pub adapter: Adapter,
+    pub device: Device,
+    pub queue: Queue,
+    pub globals_buffer: Tracked<Buffer>,
+    pub pipelines: PipelineCache,
+
+    /// `None` if the adapter can't time passes
+    pub timer: Option<GpuTimer>,
+
+    pub present_mode: PresentMode,
+    pub format: FormatRequest,
+
+    /// Set when the device runs out of memory or reports being lost
+    lost: Arc<AtomicBool>,
+}
+
+/// What windows are built with, borrowed from whatever owns the device.
+#[derive(Clone, Copy)]
+pub struct DeviceContext<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    pub pipelines: &'a PipelineCache,
+    pub globals_buffer: &'a Buffer,
+}
+
+/// A window, and a surface to draw into it.
+pub fn new_window(
+    event_loop: &EventLoopWindowTarget<()>,
+    instance: &Instance,
+    geometry: &WindowGeometry,
+    title: &str,
+) -> Result<(Window, Surface)> {
+    let window = Window::new(event_loop)?;
+    window.set_title(title);
+    geometry.apply(&window);
+    let surface = unsafe { instance.create_surface(&window) };
+
+    Ok((window, surface))
+}
+
+/// An adapter able to draw into every one of `surfaces`, and a device on it
+/// which reports being lost through `lost`.
+async fn request_device(
+    instance: &Instance,
+    surfaces: &[&Surface],
+    lost: &Arc<AtomicBool>,
+) -> Result<(Adapter, Device, Queue)> {
+    let first = surfaces.first().wrap_err("No surfaces to draw into")?;
+    let supports_all = |adapter: &Adapter| {
+        surfaces
+            .iter()
+            .all(|surface| adapter.is_surface_supported(surface))
+    };
+
+    // The default adapter, unless a window on another monitor or GPU can't use it
+    let adapter = match instance
+        .request_adapter(&RequestAdapterOptions {
+            compatible_surface: Some(first),
+            ..Default::default()
+        })
+        .await
+        .filter(supports_all)
+    {
+        Some(adapter) => adapter,
+        None => instance
+            .enumerate_adapters(Backends::all())
+            .find(supports_all)
+            .wrap_err("No adapter can draw into every window")?,
+    };
+
+    let (device, queue) = adapter
+        .request_device(
+            &DeviceDescriptor {
+                features: GpuTimer::features(adapter.features()),
+                ..Default::default()
+            },
+            None,
+        )
+        .await?;
+
+    let lost = lost.clone();
+    device.on_uncaptured_error(move |error| match error {
+        Error::OutOfMemoryError { .. } => {
+            error!("Device lost: {}", error);
+            lost.store(true, Ordering::SeqCst);
+        }
+        // Everything still used on a lost device fails validation until it is rebuilt
+        Error::ValidationError {
+            ref description, ..
+        } if lost.load(Ordering::SeqCst) || description.contains("device is lost") => {
+            error!("Device lost: {}", error);
+            lost.store(true, Ordering::SeqCst);
+        }
+        // Same as the default handler: These are bugs
+        Error::ValidationError { .. } => {
+            error!("wgpu error: {}", error);
+            panic!("Handling wgpu errors as fatal by default");
+        }
+    });
+
+    Ok((adapter, device, queue))
+}
+
+impl Gpu {
+    /// `surfaces` are those of every window to be opened.
+    pub async fn new(
+        instance: Instance,
+        surfaces: &[&Surface],
+        present_mode: PresentMode,
+        format: FormatRequest,
+    ) -> Result<Self> {
+        let lost = Arc::new(AtomicBool::new(false));
+        let (adapter, device, queue) = request_device(&instance, surfaces, &lost).await?;
+        let globals_buffer =
+            resources::create_buffer(&device, &GlobalsUniform::default().buffer_descriptor());
+        let timer = GpuTimer::new(&device, &queue);
+
+        Ok(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+            globals_buffer,
+            pipelines: PipelineCache::default(),
+            timer,
+            present_mode,
+            format,
+            lost,
+        })
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::SeqCst)
+    }
+
+    pub fn context(&self) -> DeviceContext<'_> {
+        DeviceContext {
+            device: &self.device,
+            queue: &self.queue,
+            pipelines: &self.pipelines,
+            globals_buffer: &self.globals_buffer,
+        }
+    }
+
+    pub fn viewport(&self, (window, surface): (Window, Surface)) -> Result<Viewport> {
+        Viewport::new(
+            window,
+            surface,
+            &self.adapter,
+            &self.device,
+            self.present_mode,
+            self.format,
+        )
+    }
+
+    pub fn open_window(
+        &self,
+        target: Target,
+        viewport: Viewport,
+        maps: &Maps,
+    ) -> Result<Box<dyn AppWindow>> {
+        let size = viewport.window.inner_size();
+        let texture_format = viewport.format();
+        let viewport = Some(viewport);
+
+        Ok(match target {
+            Target::Main => Box::new(WindowMain::new(
+                viewport,
+                size,
+                self.context(),
+                &texture_format,
+                maps,
+            )?),
+            Target::Extra => Box::new(WindowExtra::new(
+                viewport,
+                size,
+                self.context(),
+                &texture_format,
+                maps,
+            )?),
+            Target::Profile => Box::new(WindowProfile::new(
+                viewport,
+                size,
+                self.context(),
+                &texture_format,
+                maps,
+            )?),
+            Target::Device => return Err(eyre!("Device input has no window")),
+        })
+    }
+
+    /// Start over on a new device, e.g. after the old one was lost.
+    ///
+    /// Windows keep their surfaces and surface configuration,
+    /// everything else on the GPU is rebuilt from what the app keeps on the CPU.
+    pub fn rebuild(&mut self, app: &mut App, maps: &Maps) -> Result<()> {
+        let viewports = take_viewports(app);
+        let surfaces = viewports
+            .iter()
+            .map(|(_, viewport)| &viewport.surface)
+            .collect::<Vec<_>>();
+
+        self.lost = Arc::new(AtomicBool::new(false));
+        let (adapter, device, queue) =
+            pollster::block_on(request_device(&self.instance, &surfaces, &self.lost))?;
+        self.globals_buffer =
+            resources::create_buffer(&device, &GlobalsUniform::default().buffer_descriptor());
+        self.timer = GpuTimer::new(&device, &queue);
+        self.adapter = adapter;
+        self.device = device;
+        self.queue = queue;
+        // Pipelines from the old device would fail validation on the new one
+        self.pipelines = PipelineCache::default();
+
+        for (target, viewport) in viewports {
+            viewport.configure(&self.device);
+            app.open_window(self.open_window(target, viewport, maps)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rebuild every window from other maps, on the same device.
+    pub fn rebuild_windows(&self, app: &mut App, maps: &Maps) -> Result<()> {
+        for (target, viewport) in take_viewports(app) {
+            app.open_window(self.open_window(target, viewport, maps)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Take the viewports out of the app's windows, closing them, to build new windows in them.
+fn take_viewports(app: &mut App) -> Vec<(Target, Viewport)> {
+    app.windows
+        .drain()
+        .filter_map(|(_, window)| {
+            let target = window.target();
+            window.into_viewport().map(|viewport| (target, viewport))
+        })
+        .collect()
+}
@@ -0,0 +1,176 @@
+use std::{borrow::Cow, marker::PhantomData};
+
+use bytemuck::Pod;
+use wgpu::{
+    Buffer, BufferAddress, BufferDescriptor, BufferUsages, Device, IndexFormat, Queue, RenderPass,
+    COPY_BUFFER_ALIGNMENT,
+};
+
+use crate::{
+    mesh::{Generation, Indices, Mesh},
+    resources::{self, Tracked},
+    vertex::Vertex,
+};
+
+/// Index types meshes can be drawn with.
+pub trait MeshIndex: Pod {
+    const FORMAT: IndexFormat;
+}
+
+impl MeshIndex for u16 {
+    const FORMAT: IndexFormat = IndexFormat::Uint16;
+}
+
+impl MeshIndex for u32 {
+    const FORMAT: IndexFormat = IndexFormat::Uint32;
+}
+
+/// A buffer which is replaced by a larger one when what is written to it doesn't fit.
+struct GrowingBuffer {
+    label: String,
+    usage: BufferUsages,
+    buffer: Tracked<Buffer>,
+
+    /// How many bytes the buffer holds
+    capacity: BufferAddress,
+}
+
+impl GrowingBuffer {
+    fn new(device: &Device, label: String, usage: BufferUsages) -> Self {
+        // Empty buffers can't be bound
+        let capacity = COPY_BUFFER_ALIGNMENT;
+
+        Self {
+            buffer: create_buffer(device, &label, usage, capacity),
+            label,
+            usage,
+            capacity,
+        }
+    }
+
+    fn write(&mut self, device: &Device, queue: &Queue, data: &[u8]) {
+        // Writes must cover a multiple of COPY_BUFFER_ALIGNMENT bytes
+        let len = align(data.len() as BufferAddress);
+        let data = match len == data.len() as BufferAddress {
+            true => Cow::Borrowed(data),
+            false => {
+                let mut padded = data.to_vec();
+                padded.resize(len as usize, 0);
+                Cow::Owned(padded)
+            }
+        };
+
+        if self.capacity < len {
+            self.capacity = len.next_power_of_two();
+            self.buffer = create_buffer(device, &self.label, self.usage, self.capacity);
+        }
+        queue.write_buffer(&self.buffer, 0, &data);
+    }
+}
+
+fn create_buffer(
+    device: &Device,
+    label: &str,
+    usage: BufferUsages,
+    size: BufferAddress,
+) -> Tracked<Buffer> {
+    resources::create_buffer(
+        device,
+        &BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: usage | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        },
+    )
+}
+
+fn align(len: BufferAddress) -> BufferAddress {
+    len.div_ceil(COPY_BUFFER_ALIGNMENT) * COPY_BUFFER_ALIGNMENT
+}
+
+/// Vertices and indices kept on the GPU.
+///
+/// The buffers grow when the mesh outgrows them, and are never shrunk.
+/// Each upload may use either index type.
+pub struct GpuMesh<V> {
+    vertices: GrowingBuffer,
+    indices: GrowingBuffer,
+    index_format: IndexFormat,
+    index_count: u32,
+
+    /// The generation of the [`Mesh`] last uploaded, if it came from one
+    generation: Option<Generation>,
+    vertex: PhantomData<V>,
+}
+
+impl<V: Pod> GpuMesh<V> {
+    /// Empty until the first upload.
+    pub fn new(device: &Device, label: &str) -> Self {
+        Self {
+            vertices: GrowingBuffer::new(
+                device,
+                format!("{} vertex buffer", label),
+                BufferUsages::VERTEX,
+            ),
+            indices: GrowingBuffer::new(
+                device,
+                format!("{} index buffer", label),
+                BufferUsages::INDEX,
+            ),
+            index_format: IndexFormat::Uint16,
+            index_count: 0,
+            generation: None,
+            vertex: PhantomData,
+        }
+    }
+
+    /// Write the vertices and indices, for meshes built anew every frame.
+    pub fn upload<I: MeshIndex>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        vertices: &[V],
+        indices: &[I],
+    ) {
+        self.vertices
+            .write(device, queue, bytemuck::cast_slice(vertices));
+        self.indices
+            .write(device, queue, bytemuck::cast_slice(indices));
+        self.index_format = I::FORMAT;
+        self.index_count = indices.len() as u32;
+        self.generation = None;
+    }
+
+    /// Set the vertex and index buffers, to draw parts of the mesh.
+    pub fn bind<'a>(&'a self, rpass: &mut RenderPass<'a>) {
+        rpass.set_index_buffer(self.indices.buffer.slice(..), self.index_format);
+        rpass.set_vertex_buffer(0, self.vertices.buffer.slice(..));
+    }
+
+    /// Draw the whole mesh, if there is anything to draw.
+    pub fn draw<'a>(&'a self, rpass: &mut RenderPass<'a>) {
+        if self.index_count == 0 {
+            return;
+        }
+
+        self.bind(rpass);
+        rpass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
+
+impl GpuMesh<Vertex> {
+    /// [`GpuMesh::upload`] a mesh, with whichever index type it has,
+    /// unless this generation of it is already uploaded.
+    pub fn upload_mesh(&mut self, device: &Device, queue: &Queue, mesh: &Mesh) {
+        if self.generation == Some(mesh.generation()) {
+            return;
+        }
+
+        match mesh.indices() {
+            Indices::U16(indices) => self.upload(device, queue, mesh.vertices(), indices),
+            Indices::U32(indices) => self.upload(device, queue, mesh.vertices(), indices),
+        }
+        self.generation = Some(mesh.generation());
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::Path;
+
+use color_eyre::{
+    eyre::{eyre, ContextCompat},
+    Result,
+};
+use log::info;
+use wgpu::*;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    app_window::AppWindow,
+    camera_path::CameraPath,
+    cli::{Cli, HeadlessWindow},
+    export::Exporter,
+    globals::GlobalsUniform,
+    gpu::DeviceContext,
+    offscreen::Offscreen,
+    pipeline::PipelineCache,
+    resources::{self, Tracked},
+    scene::Scene,
+    session::Session,
+    window_extra::WindowExtra,
+    window_main::WindowMain,
+    window_profile::WindowProfile,
+};
+
+/// What headless runs render into.
+const TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// How long exports without a camera path or explicit length are, in seconds.
+const DEFAULT_EXPORT_SECONDS: f32 = 5.0;
+
+/// The device, the scene and a window without a surface.
+struct Headless {
+    device: Device,
+    queue: Queue,
+    globals_buffer: Tracked<Buffer>,
+
+    /// One window's pipelines, never rebuilt
+    pipelines: PipelineCache,
+    size: PhysicalSize<u32>,
+    offscreen: Offscreen,
+    scene: Scene,
+    window: Box<dyn AppWindow>,
+}
+
+impl Headless {
+    async fn new(cli: &Cli) -> Result<Self> {
+        let instance = Instance::new(cli.graphics.backend.into());
+
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                compatible_surface: None,
+                ..Default::default()
+            })
+            .await
+            .wrap_err("No adapter")?;
+
+        let (device, queue) = adapter
+            .request_device(
+                &DeviceDescriptor {
+                    ..Default::default()
+                },
+                None,
+            )
+            .await?;
+
+        let globals_buffer =
+            resources::create_buffer(&device, &GlobalsUniform::default().buffer_descriptor());
+
+        // Never from the last session, so runs only depend on their arguments
+        let mut session = Session::default();
+        cli.apply(&mut session);
+
+        let size = match cli.headless.window {
+            HeadlessWindow::Main => session.main_window.size,
+            HeadlessWindow::Extra => session.extra_window.size,
+            HeadlessWindow::Profile => session.profile_window.size,
+        }
+        .to_physical(1.0);
+        let offscreen = Offscreen::new(&device, size, TEXTURE_FORMAT);
+
+        let pipelines = PipelineCache::default();
+        let context = DeviceContext {
+            device: &device,
+            queue: &queue,
+            pipelines: &pipelines,
+            globals_buffer: &globals_buffer,
+        };
+
+        let window: Box<dyn AppWindow> = match cli.headless.window {
+            HeadlessWindow::Main => Box::new(WindowMain::new(
+                None,
+                size,
+                context,
+                &TEXTURE_FORMAT,
+                &session.maps,
+            )?),
+            HeadlessWindow::Extra => Box::new(WindowExtra::new(
+                None,
+                size,
+                context,
+                &TEXTURE_FORMAT,
+                &session.maps,
+            )?),
+            HeadlessWindow::Profile => Box::new(WindowProfile::new(
+                None,
+                size,
+                context,
+                &TEXTURE_FORMAT,
+                &session.maps,
+            )?),
+        };
+
+        let scene = Scene::new(session.main, session.radar);
+
+        Ok(Self {
+            device,
+            queue,
+            globals_buffer,
+            pipelines,
+            size,
+            offscreen,
+            scene,
+            window,
+        })
+    }
+
+    fn render(&mut self, globals: &GlobalsUniform) {
+        self.queue
+            .write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(globals));
+
+        let context = DeviceContext {
+            device: &self.device,
+            queue: &self.queue,
+            pipelines: &self.pipelines,
+            globals_buffer: &self.globals_buffer,
+        };
+        self.window
+            .render_to(context, &self.scene, &self.offscreen.view);
+    }
+}
+
+/// Whether the command line asks to run without windows.
+pub fn requested(cli: &Cli) -> bool {
+    cli.headless.headless.is_some() || cli.headless.export.is_some()
+}
+
+/// Render a window offscreen and save the result.
+pub async fn run(cli: &Cli) -> Result<()> {
+    let mut headless = Headless::new(cli).await?;
+
+    if let Some(path) = &cli.headless.headless {
+        headless.render(&GlobalsUniform::default());
+        headless
+            .offscreen
+            .save_png(&headless.device, &headless.queue, path)?;
+        info!("Saved {:?} window to {:?}", cli.headless.window, path);
+    }
+
+    if let Some(path) = &cli.headless.export {
+        export(&mut headless, cli, path)?;
+    }
+
+    Ok(())
+}
+
+/// Fly along the camera path with a fixed timestep, writing every frame.
+/// Nothing depends on wall clock time, so the same arguments give the same output.
+fn export(headless: &mut Headless, cli: &Cli, path: &Path) -> Result<()> {
+    if let Some(camera_path) = &cli.headless.path {
+        headless.scene.camera_path = CameraPath::load(camera_path)?;
+    }
+    let camera_path = headless.scene.camera_path.clone();
+
+    let fps = cli.headless.fps;
+    let delta = 1.0 / fps as f32;
+    let frames = match (cli.headless.frames, cli.headless.seconds) {
+        (Some(frames), _) => frames,
+        // At least one frame, however short
+        (None, Some(seconds)) => ((seconds * fps as f32).round() as u32).max(1),
+        (None, None) if camera_path.length() > 0.0 => {
+            let duration = camera_path.duration();
+            if !duration.is_finite() || duration <= 0.0 {
+                return Err(eyre!(
+                    "Camera path can't be flown along at speed {}",
+                    camera_path.speed
+                ));
+            }
+
+            // A looping path's last frame would repeat the first
+            let frames = duration * fps as f32;
+            if camera_path.looping {
+                frames.floor() as u32
+            } else {
+                frames.ceil() as u32 + 1
+            }
+        }
+        (None, None) => (DEFAULT_EXPORT_SECONDS * fps as f32).round() as u32,
+    };
+
+    let mut exporter = Exporter::new(path, fps, headless.size.width, headless.size.height)?;
+
+    for frame in 0..frames {
+        let time = frame as f32 * delta;
+
+        if let Some(keyframe) = camera_path.sample(time * camera_path.speed) {
+            headless.scene.fly_to(&keyframe);
+        }
+
+        let globals = GlobalsUniform {
+            time,
+            delta,
+            frame,
+            ..Default::default()
+        };
+        headless.render(&globals);
+
+        exporter.push(headless.offscreen.read(&headless.device, &headless.queue)?)?;
+    }
+
+    let written = exporter.finish()?;
+    println!("Exported {} frames to {}", written, path.display());
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use std::collections::HashSet;
+
+use winit::event::{ElementState, VirtualKeyCode};
+
+/// Tracks which keys are currently held down.
+#[derive(Debug, Default)]
+pub struct InputState {
+    held: HashSet<VirtualKeyCode>,
+}
+
+impl InputState {
+    /// Record a key event.
+    /// Returns true if the key went from released to pressed,
+    /// i.e. false for releases and for the OS repeating a held key.
+    pub fn handle_key(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
+        match state {
+            ElementState::Pressed => self.held.insert(key),
+            ElementState::Released => {
+                self.held.remove(&key);
+                false
+            }
+        }
+    }
+
+    pub fn is_held(&self, key: VirtualKeyCode) -> bool {
+        self.held.contains(&key)
+    }
+
+    pub fn any_held(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    /// Forget all held keys.
+    /// Used when losing focus, since we won't see the releases.
+    pub fn clear(&mut self) {
+        self.held.clear();
+    }
+}
@@ -0,0 +1,587 @@
+use std::{path::PathBuf, time::Instant};
+
+use bytemuck::{Pod, Zeroable};
+use color_eyre::Result;
+use egui::{
+    ClippedMesh, CollapsingHeader, CtxRef, DragValue, Grid, ScrollArea, SidePanel, Slider,
+    TextureId, Ui,
+};
+use egui_winit_platform::{Platform, PlatformDescriptor};
+use wgpu::*;
+use winit::{
+    dpi::PhysicalSize,
+    event::{ElementState, Event, KeyboardInput, WindowEvent},
+    window::{Window, WindowId},
+};
+
+use crate::{
+    app_window::Context,
+    camera::{FOV_MAX, FOV_MIN, HEIGHT_MAX, PITCH_MAX, VIEWING_DISTANCE_MIN},
+    gpu_mesh::GpuMesh,
+    pipeline::{BindLayout, PipelineBuilder, PipelineCache, Shader, SharedPipeline},
+    scene::Scene,
+    session::Maps,
+    text,
+    texture_image::{self, TextureImage},
+    vertex_layout::VertexLayout,
+    viewport, window_main,
+};
+
+/// Width the panel starts out with, in logical pixels.
+const PANEL_WIDTH: f32 = 280.0;
+
+/// The largest vertex step size the slider goes up to.
+const DISPLACE_AMOUNT_MAX: f32 = 0.5;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable, VertexLayout, Default, PartialEq)]
+struct InspectorVertex {
+    pos: [f32; 2],
+    tc: [f32; 2],
+
+    /// sRGBA with premultiplied alpha, as egui gives it
+    #[vertex(format = "Unorm8x4")]
+    colour: [u8; 4],
+}
+
+/// egui's font texture, uploaded again whenever egui changes it.
+struct FontTexture {
+    version: u64,
+
+    #[allow(dead_code)]
+    image: TextureImage,
+    bind_group: BindGroup,
+}
+
+fn render_pipeline(
+    device: &Device,
+    pipelines: &PipelineCache,
+    layout: &BindLayout,
+    format: &TextureFormat,
+) -> Result<SharedPipeline> {
+    let fragment = match viewport::is_srgb(*format) {
+        true => "fs_main_srgb",
+        false => "fs_main",
+    };
+
+    PipelineBuilder::new(
+        "Inspector render pipeline",
+        Shader::Static {
+            label: "Inspector shader",
+            source: include_str!("shaders/inspector.wgsl"),
+        },
+        *format,
+    )
+    .layout("Inspector pipeline layout", &[layout])
+    .entry_points("vs_main", fragment)
+    .vertex::<InspectorVertex>()
+    .blend(BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+    .build(device, pipelines)
+}
+
+/// An egui panel docked over a window, editing the scene as it is drawn.
+///
+/// Edits are recorded by the app as the scene they leave behind, so replays see them too.
+pub struct Inspector {
+    /// Whether the panel is drawn and takes input
+    pub shown: bool,
+
+    platform: Platform,
+    start: Instant,
+    size: PhysicalSize<u32>,
+    scale_factor: f64,
+
+    /// The maps the windows were built from, and the paths being typed in to replace them
+    maps: Maps,
+    diffuse_map: String,
+    height_map: String,
+
+    /// What to draw, from the last time the panel was laid out
+    meshes: Vec<ClippedMesh>,
+
+    render_pipeline: SharedPipeline,
+    bind_group_layout: BindLayout,
+    font: Option<FontTexture>,
+    mesh: GpuMesh<InspectorVertex>,
+}
+
+impl Inspector {
+    pub fn new(
+        device: &Device,
+        pipelines: &PipelineCache,
+        format: &TextureFormat,
+        size: PhysicalSize<u32>,
+        scale_factor: f64,
+        maps: &Maps,
+    ) -> Result<Self> {
+        let platform = Platform::new(PlatformDescriptor {
+            physical_width: size.width,
+            physical_height: size.height,
+            scale_factor,
+            ..Default::default()
+        });
+        let bind_group_layout = text::bind_group_layout(device);
+
+        Ok(Self {
+            shown: false,
+            platform,
+            start: Instant::now(),
+            size,
+            scale_factor,
+            maps: maps.clone(),
+            diffuse_map: maps.diffuse_map.display().to_string(),
+            height_map: maps.height_map.display().to_string(),
+            meshes: vec![],
+            render_pipeline: render_pipeline(device, pipelines, &bind_group_layout, format)?,
+            bind_group_layout,
+            font: None,
+            mesh: GpuMesh::new(device, "Inspector"),
+        })
+    }
+
+    pub fn set_texture_format(
+        &mut self,
+        device: &Device,
+        pipelines: &PipelineCache,
+        format: &TextureFormat,
+    ) -> Result<()> {
+        self.render_pipeline = render_pipeline(device, pipelines, &self.bind_group_layout, format)?;
+
+        Ok(())
+    }
+
+    /// Also called when the scale factor changes.
+    pub fn resize(&mut self, size: PhysicalSize<u32>, scale_factor: f64) {
+        self.size = size;
+        self.scale_factor = scale_factor;
+
+        // Sets both the scale factor and the size, which egui keeps in logical pixels
+        let mut new_inner_size = size;
+        self.platform.handle_event::<()>(&Event::WindowEvent {
+            // Safe, egui ignores which window an event is for
+            window_id: unsafe { WindowId::dummy() },
+            event: WindowEvent::ScaleFactorChanged {
+                scale_factor,
+                new_inner_size: &mut new_inner_size,
+            },
+        });
+    }
+
+    /// Hand a window event to egui while the panel is shown.
+    /// Returns true if egui takes it, e.g. a click on the panel or typing into a field.
+    /// Releases are never taken, so keys and buttons pressed before don't stay held.
+    pub fn capture_event(&mut self, event: &Event<()>) -> bool {
+        if !self.shown {
+            return false;
+        }
+
+        self.platform.handle_event(event);
+
+        match event {
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Released,
+                                ..
+                            },
+                        ..
+                    }
+                    | WindowEvent::MouseInput {
+                        state: ElementState::Released,
+                        ..
+                    },
+                ..
+            } => false,
+            event => self.platform.captures_event(event),
+        }
+    }
+
+    /// Lay out the panel, applying edits to the scene.
+    /// `window` gets egui's mouse cursor, if given.
+    /// Returns true if egui wants another frame, e.g. for an animation.
+    pub fn update(&mut self, context: &mut Context, window: Option<&Window>) -> bool {
+        if !self.shown {
+            self.meshes.clear();
+            return false;
+        }
+
+        self.platform
+            .update_time(self.start.elapsed().as_secs_f64());
+        self.platform.begin_frame();
+
+        let ctx = self.platform.context();
+        self.edit(&ctx, context);
+
+        let (output, shapes) = self.platform.end_frame(window);
+        self.meshes = ctx.tessellate(shapes);
+
+        output.needs_repaint
+    }
+
+    fn edit(&mut self, ctx: &CtxRef, context: &mut Context) {
+        SidePanel::right("Inspector")
+            .default_width(PANEL_WIDTH)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().show(ui, |ui| {
+                    edit_camera(ui, context.scene);
+                    edit_radar(ui, context.scene);
+                    edit_square(ui, context.scene);
+                    self.edit_maps(ui, context);
+                });
+            });
+    }
+
+    fn edit_maps(&mut self, ui: &mut Ui, context: &mut Context) {
+        CollapsingHeader::new("Maps")
+            .default_open(true)
+            .show(ui, |ui| {
+                Grid::new("Maps").num_columns(2).show(ui, |ui| {
+                    ui.label("Colour map");
+                    ui.text_edit_singleline(&mut self.diffuse_map);
+                    ui.end_row();
+
+                    ui.label("Height map");
+                    ui.text_edit_singleline(&mut self.height_map);
+                    ui.end_row();
+                });
+
+                let maps = Maps {
+                    diffuse_map: PathBuf::from(&self.diffuse_map),
+                    height_map: PathBuf::from(&self.height_map),
+                    ..self.maps.clone()
+                };
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(maps != self.maps, egui::Button::new("Apply"))
+                        .on_hover_text("Rebuild the windows from these maps")
+                        .clicked()
+                    {
+                        *context.pending_maps = Some(maps);
+                    }
+                    if ui.button("Revert").clicked() {
+                        self.diffuse_map = self.maps.diffuse_map.display().to_string();
+                        self.height_map = self.maps.height_map.display().to_string();
+                    }
+                });
+            });
+    }
+
+    /// Draw the panel over what is already in the window.
+    pub fn render(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        texture_view: &TextureView,
+    ) {
+        if self.meshes.is_empty() {
+            return;
+        }
+        self.update_font(device, queue);
+
+        let font = match &self.font {
+            Some(font) => font,
+            None => return,
+        };
+
+        // egui lays out in points from the top left, scissor rects are in physical pixels
+        let pixels_per_point = self.scale_factor as f32;
+        let (width, height) = (
+            self.size.width.max(1) as f32 / pixels_per_point,
+            self.size.height.max(1) as f32 / pixels_per_point,
+        );
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        let mut draws = vec![];
+
+        for ClippedMesh(clip, mesh) in &self.meshes {
+            // Only egui's own texture is used
+            if mesh.texture_id != TextureId::Egui || mesh.indices.is_empty() {
+                continue;
+            }
+
+            let min_x = (clip.min.x * pixels_per_point).round().max(0.0) as u32;
+            let min_y = (clip.min.y * pixels_per_point).round().max(0.0) as u32;
+            let max_x = ((clip.max.x * pixels_per_point).round() as u32).min(self.size.width);
+            let max_y = ((clip.max.y * pixels_per_point).round() as u32).min(self.size.height);
+            if max_x <= min_x || max_y <= min_y {
+                continue;
+            }
+
+            let first = indices.len() as u32;
+            let base = vertices.len() as i32;
+            vertices.extend(mesh.vertices.iter().map(|vertex| InspectorVertex {
+                pos: [
+                    vertex.pos.x / width * 2.0 - 1.0,
+                    1.0 - vertex.pos.y / height * 2.0,
+                ],
+                tc: [vertex.uv.x, vertex.uv.y],
+                colour: vertex.color.to_array(),
+            }));
+            indices.extend_from_slice(&mesh.indices);
+
+            draws.push((
+                [min_x, min_y, max_x - min_x, max_y - min_y],
+                first..indices.len() as u32,
+                base,
+            ));
+        }
+
+        if draws.is_empty() {
+            return;
+        }
+
+        self.mesh.upload(device, queue, &vertices, &indices);
+
+        encoder.push_debug_group("Inspector");
+        let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Inspector render pass"),
+            color_attachments: &[RenderPassColorAttachment {
+                view: texture_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, &font.bind_group, &[]);
+        self.mesh.bind(&mut rpass);
+        rpass.insert_debug_marker("Draw panel");
+        for ([x, y, width, height], indices, base) in draws {
+            rpass.set_scissor_rect(x, y, width, height);
+            rpass.draw_indexed(indices, base, 0..1);
+        }
+
+        drop(rpass);
+        encoder.pop_debug_group();
+    }
+
+    /// Upload egui's font texture if it changed since it was last uploaded.
+    fn update_font(&mut self, device: &Device, queue: &Queue) {
+        let texture = self.platform.context().texture();
+        if matches!(&self.font, Some(font) if font.version == texture.version) {
+            return;
+        }
+
+        // White, with egui's coverage as alpha
+        let pixels = texture
+            .pixels
+            .iter()
+            .flat_map(|alpha| [255, 255, 255, *alpha])
+            .collect::<Vec<_>>();
+        let image = match TextureImage::new(
+            "Inspector font",
+            device,
+            texture.width,
+            texture.height,
+            &pixels,
+        ) {
+            Ok(image) => image,
+            Err(e) => {
+                eprintln!("Error creating inspector font texture: {:#?}", e);
+                return;
+            }
+        };
+        image.write(queue);
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Inspector bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&image.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&texture_image::nearest_sampler(device)),
+                },
+            ],
+        });
+
+        self.font = Some(FontTexture {
+            version: texture.version,
+            image,
+            bind_group,
+        });
+    }
+}
+
+fn edit_camera(ui: &mut Ui, scene: &mut Scene) {
+    let camera = &mut scene.camera;
+
+    CollapsingHeader::new("Camera")
+        .default_open(true)
+        .show(ui, |ui| {
+            Grid::new("Camera").num_columns(2).show(ui, |ui| {
+                ui.label("x");
+                ui.add(Slider::new(&mut camera.x, -1.0..=1.0));
+                ui.end_row();
+
+                ui.label("y");
+                ui.add(Slider::new(&mut camera.y, -1.0..=1.0));
+                ui.end_row();
+
+                ui.label("Height");
+                ui.add(Slider::new(&mut camera.height, 0.0..=HEIGHT_MAX));
+                ui.end_row();
+
+                ui.label("Viewing distance");
+                ui.add(Slider::new(
+                    &mut camera.viewing_distance,
+                    VIEWING_DISTANCE_MIN..=1.0,
+                ));
+                ui.end_row();
+
+                // The direction follows the angle
+                ui.label("Viewing angle");
+                let mut angle = camera.viewing_angle;
+                if ui
+                    .add(Slider::new(
+                        &mut angle,
+                        -std::f32::consts::PI..=std::f32::consts::PI,
+                    ))
+                    .changed()
+                {
+                    camera.rotate(angle - camera.viewing_angle);
+                }
+                ui.end_row();
+
+                ui.label("Viewing direction");
+                let [x, y] = camera.viewing_dir.as_array();
+                ui.monospace(format!("({:.3}, {:.3})", x, y));
+                ui.end_row();
+
+                ui.label("Field of view");
+                ui.add(Slider::new(&mut camera.fov, FOV_MIN..=FOV_MAX));
+                ui.end_row();
+
+                ui.label("Pitch");
+                ui.add(Slider::new(&mut camera.pitch, -PITCH_MAX..=PITCH_MAX));
+                ui.end_row();
+
+                ui.label("Velocity");
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut camera.velocity[0]).speed(0.01));
+                    ui.add(DragValue::new(&mut camera.velocity[1]).speed(0.01));
+                });
+                ui.end_row();
+
+                ui.label("Angular velocity");
+                ui.add(DragValue::new(&mut camera.angular_velocity).speed(0.01));
+                ui.end_row();
+
+                let motion = &mut camera.motion;
+
+                ui.label("Acceleration");
+                ui.add(
+                    DragValue::new(&mut motion.acceleration)
+                        .speed(0.1)
+                        .clamp_range(0.0..=f32::MAX),
+                );
+                ui.end_row();
+
+                ui.label("Angular acceleration");
+                ui.add(
+                    DragValue::new(&mut motion.angular_acceleration)
+                        .speed(0.1)
+                        .clamp_range(0.0..=f32::MAX),
+                );
+                ui.end_row();
+
+                ui.label("Damping");
+                ui.add(
+                    DragValue::new(&mut motion.damping)
+                        .speed(0.1)
+                        .clamp_range(0.0..=f32::MAX),
+                );
+                ui.end_row();
+            });
+        });
+}
+
+fn edit_radar(ui: &mut Ui, scene: &mut Scene) {
+    let radar = &mut scene.radar;
+
+    CollapsingHeader::new("Radar")
+        .default_open(true)
+        .show(ui, |ui| {
+            Grid::new("Radar").num_columns(2).show(ui, |ui| {
+                ui.label("Field of view");
+                ui.add(Slider::new(&mut radar.fov[0], 0.0..=std::f32::consts::PI));
+                ui.end_row();
+
+                // Replaced by the camera's when drawn
+                ui.label("View direction");
+                ui.monospace(format!(
+                    "({:.3}, {:.3}), follows the camera",
+                    radar.view_dir[0], radar.view_dir[1]
+                ));
+                ui.end_row();
+
+                ui.label("Position");
+                ui.monospace(format!(
+                    "({:.3}, {:.3}), follows the camera",
+                    radar.position[0], radar.position[1]
+                ));
+                ui.end_row();
+            });
+        });
+}
+
+fn edit_square(ui: &mut Ui, scene: &mut Scene) {
+    CollapsingHeader::new("Square")
+        .default_open(true)
+        .show(ui, |ui| {
+            Grid::new("Square").num_columns(2).show(ui, |ui| {
+                ui.label("Step size");
+                ui.add(Slider::new(
+                    &mut scene.displace_amount,
+                    window_main::DIFF..=DISPLACE_AMOUNT_MAX,
+                ));
+                ui.end_row();
+
+                let square = &mut scene.square;
+
+                ui.label("Mesh");
+                ui.monospace(format!(
+                    "{} vertices, {:?}",
+                    square.mesh.vertices().len(),
+                    square.mesh.topology
+                ));
+                ui.end_row();
+
+                let last = square.mesh.vertices().len().saturating_sub(1);
+
+                ui.label("Selected vertex");
+                ui.add(DragValue::new(&mut square.selected).clamp_range(0..=last));
+                ui.end_row();
+
+                let Some(vertex) = square.mesh.vertices().get(square.selected) else {
+                    return;
+                };
+                // Only written back when moved, so the mesh isn't uploaded again every frame
+                let mut pos = vertex.pos;
+
+                ui.label("x");
+                let moved = ui.add(Slider::new(&mut pos[0], -1.0..=1.0)).changed();
+                ui.end_row();
+
+                ui.label("y");
+                let moved = ui.add(Slider::new(&mut pos[1], -1.0..=1.0)).changed() || moved;
+                ui.end_row();
+
+                if moved {
+                    square.mesh.move_vertex(square.selected, pos[0], pos[1]);
+                }
+            });
+        });
+}
@@ -3,101 +3,142 @@ use std::{
     time::{Duration, Instant},
 };
 
-use color_eyre::{eyre::ContextCompat, Result};
-use log::debug;
+use app::App;
+use clap::Parser;
+use cli::Cli;
+use color_eyre::Result;
+use gpu::{new_window, Gpu};
+use log::{debug, info};
 use notify::RecursiveMode;
-use viewport::Viewport;
+use recording::Target;
+use scene::Scene;
+use scheduler::{FrameMode, Scheduler};
+use session::Session;
 use wgpu::*;
-use window_extra::WindowExtra;
-use window_main::WindowMain;
-use winit::{dpi::{PhysicalPosition, PhysicalSize}, event::{Event, KeyboardInput, WindowEvent}, event_loop::{ControlFlow, EventLoop}, window::Window};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+};
 
 mod window_extra;
 mod window_main;
+mod window_profile;
 
+mod app;
+mod app_window;
+mod bindings;
 mod camera;
+mod camera_path;
+mod cli;
+mod export;
+mod globals;
+mod gpu;
+mod gpu_mesh;
+mod headless;
+mod input;
+#[cfg(feature = "inspector")]
+mod inspector;
+mod mesh;
 mod misc;
+mod offscreen;
+mod overlay;
+mod pipeline;
+mod profile;
+mod profiler;
 mod radar;
+mod recording;
 mod resolution;
+mod resources;
+mod scene;
+mod scheduler;
+mod screenshot;
+mod session;
 mod square;
+mod text;
 mod texture_image;
 mod vec;
 mod vertex;
+mod vertex_layout;
 mod viewport;
 
-async fn run() -> Result<()> {
-    let instance = Instance::new(Backends::PRIMARY);
+async fn run(cli: Cli) -> Result<()> {
+    let instance = Instance::new(cli.graphics.backend.into());
 
     let event_loop = EventLoop::new();
 
-    let window_extra = Window::new(&event_loop)?;
-    // window_extra.set_inner_size(PhysicalSize::new(1200, 600));
-    window_extra.set_outer_position(PhysicalPosition::new(400.0, 300.0));
-
-    let window_main = Window::new(&event_loop)?;
-    window_main.set_inner_size(PhysicalSize::new(600, 600));
-    window_main.set_outer_position(PhysicalPosition::new(0.0, 300.0));
-
-    let surface = unsafe { instance.create_surface(&window_main) };
-    let adapter = instance
-        .request_adapter(&RequestAdapterOptions {
-            compatible_surface: Some(&surface),
-            ..Default::default()
-        })
-        .await
-        .wrap_err("No adapter")?;
-
-    let (device, queue) = adapter
-        .request_device(
-            &DeviceDescriptor {
-                ..Default::default()
-            },
-            None,
-        )
-        .await?;
-
-    let texture_format = surface
-        .get_preferred_format(&adapter)
-        .wrap_err("No preferred format for swap chain")?;
-
-    let mut main = WindowMain::new(
-        Viewport::new(window_main, &instance, &adapter, &device)?,
-        &device,
-        &queue,
-        &texture_format,
-    )?;
+    let mut session = match cli.no_session {
+        true => Session::default(),
+        false => Session::load(&cli.session),
+    };
+    cli.apply(&mut session);
 
-    let mut extra = WindowExtra::new(
-        Viewport::new(window_extra, &instance, &adapter, &device)?,
-        &device,
-        &queue,
-        &texture_format,
+    let window_profile = new_window(
+        &event_loop,
+        &instance,
+        &session.profile_window,
+        &app_window::title(Target::Profile),
+    )?;
+    let window_extra = new_window(
+        &event_loop,
+        &instance,
+        &session.extra_window,
+        &app_window::title(Target::Extra),
     )?;
+    let window_main = new_window(
+        &event_loop,
+        &instance,
+        &session.main_window,
+        &app_window::title(Target::Main),
+    )?;
+
+    let mut gpu = Gpu::new(
+        instance,
+        &[&window_main.1, &window_extra.1, &window_profile.1],
+        cli.graphics.present_mode.into(),
+        cli.graphics.surface_format.into(),
+    )
+    .await?;
+
+    let mut app = App::new(
+        Scene::new(session.main.clone(), session.radar),
+        Scheduler::new(FrameMode::OnDemand, cli.graphics.fps_cap),
+        cli.screenshot_dir.clone(),
+        cli.layout.clone(),
+        cli.bindings.clone(),
+    );
+    for (target, window) in [
+        (Target::Main, window_main),
+        (Target::Extra, window_extra),
+        (Target::Profile, window_profile),
+    ] {
+        app.open_window(gpu.open_window(target, gpu.viewport(window)?, &session.maps)?)?;
+    }
 
     let (watch_tx, watch_rx) = mpsc::channel();
     let mut shader_watcher = notify::watcher(watch_tx, Duration::from_millis(250))?;
 
     use notify::Watcher;
-    shader_watcher.watch(
-        // concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/radar.wgsl"),
-        &main.shader_path,
-        RecursiveMode::NonRecursive,
-    )?;
-
-    event_loop.run(move |event, _, control_flow| {
-        let _ = (&instance, &adapter);
+    shader_watcher.watch(&session.maps.shader, RecursiveMode::NonRecursive)?;
+    // Running with the default bindings, there is nothing to reload
+    if app.bindings_path.exists() {
+        shader_watcher.watch(&app.bindings_path, RecursiveMode::NonRecursive)?;
+    }
 
-        *control_flow = ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(250));
+    event_loop.run(move |event, event_loop, control_flow| {
         use notify::DebouncedEvent;
 
-        if let Ok(DebouncedEvent::Write(_)) = watch_rx.try_recv() {
-            if let Err(e) = main.reload_shader(&device) {
-                eprintln!("Error reloading shader: {:#?}", e);
+        if let Ok(DebouncedEvent::Write(path)) = watch_rx.try_recv() {
+            if path == app.bindings_path {
+                app.reload_bindings();
             } else {
-                main.viewport.window.request_redraw();
+                app.reload(&gpu.device, &gpu.pipelines, &path);
             }
         }
 
+        if app.capture_event(&event) {
+            return;
+        }
+
         match event {
             Event::WindowEvent {
                 window_id,
@@ -105,58 +146,130 @@ async fn run() -> Result<()> {
                 ..
             } => {
                 debug!("Resize: {:?}, id: {:?}", size, window_id);
-
-                if window_id == main.viewport.window.id() {
-                    main.resize(&adapter, &device, size);
-                } else if window_id == extra.viewport.window.id() {
-                    extra.viewport.resize(&adapter, &device, size);
-                } else {
-                    panic!("OTHER WINDOW???");
-                }
+                app.resize(window_id, &gpu.device, size);
             }
 
             Event::WindowEvent {
                 window_id,
                 event:
-                    WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                virtual_keycode: Some(key),
-                                ..
-                            },
-                        ..
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
                     },
-                ..
             } => {
-                debug!("Pressed: {:?}, id: {:?}", key, window_id);
-
-                if window_id == main.viewport.window.id() {
-                    main.handle_key(key);
-                    // main.push_resources(&queue).unwrap();
-                } else if window_id == extra.viewport.window.id() {
-                    extra.handle_key(key);
-                } else {
-                    panic!("OTHER WINDOW???");
+                debug!("Scale factor: {}, id: {:?}", scale_factor, window_id);
+
+                // Winit picks a new inner size keeping the logical size
+                app.resize(window_id, &gpu.device, *new_inner_size);
+                app.scheduler.request_frame();
+            }
+
+            Event::WindowEvent {
+                window_id,
+                event: WindowEvent::CloseRequested,
+            } => {
+                // Remember where it was, in case it's reopened or this was the last one
+                session.update(&app);
+
+                if app.close_window(window_id) {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+
+            Event::WindowEvent { window_id, event } => app.handle_window_event(window_id, &event),
+
+            Event::DeviceEvent { event, .. } => app.handle_device_event(&event),
+
+            Event::MainEventsCleared => {
+                if std::mem::take(&mut app.reopen_windows) {
+                    for (target, geometry) in [
+                        (Target::Main, session.main_window),
+                        (Target::Extra, session.extra_window),
+                        (Target::Profile, session.profile_window),
+                    ] {
+                        if app.window_of(target).is_some() {
+                            continue;
+                        }
+
+                        let title = app_window::title(target);
+                        let window = new_window(event_loop, &gpu.instance, &geometry, &title)
+                            .and_then(|window| gpu.viewport(window))
+                            .and_then(|viewport| gpu.open_window(target, viewport, &session.maps))
+                            .and_then(|window| app.open_window(window));
+                        if let Err(e) = window {
+                            eprintln!("Error reopening {:?} window: {:#?}", target, e);
+                        }
+                    }
+                }
+
+                if let Some(maps) = app.pending_maps.take() {
+                    match maps.check() {
+                        Ok(()) => {
+                            info!("Rebuilding windows from {:?}", maps);
+
+                            if let Err(e) = gpu.rebuild_windows(&mut app, &maps) {
+                                eprintln!("Error rebuilding windows: {:#?}", e);
+                                *control_flow = ControlFlow::Exit;
+                                return;
+                            }
+                            session.maps = maps;
+                            app.scheduler.request_frame();
+                        }
+                        Err(e) => eprintln!("Error loading maps: {:#?}", e),
+                    }
+                }
+
+                if gpu.is_lost() || std::mem::take(&mut app.rebuild_gpu) {
+                    info!("Rebuilding on a new device");
+
+                    if let Err(e) = gpu.rebuild(&mut app, &session.maps) {
+                        eprintln!("Error rebuilding on a new device: {:#?}", e);
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                    app.scheduler.request_frame();
+                }
+
+                app.change_surface(&gpu.device, &gpu.pipelines);
+
+                let now = Instant::now();
+
+                if let Some(globals) = app.frame(now) {
+                    if let Some(timer) = &gpu.timer {
+                        timer.collect(&gpu.device, &mut app.profiler);
+                    }
+                    gpu.queue
+                        .write_buffer(&gpu.globals_buffer, 0, bytemuck::bytes_of(&globals));
+                    app.take_screenshot(gpu.context());
+
+                    app.request_redraw();
                 }
+                app.update_titles(now);
+
+                *control_flow = app.scheduler.control_flow(now);
             }
 
             Event::RedrawRequested(window_id) => {
                 debug!("Redraw on id {:?}", window_id);
-                if window_id == main.viewport.window.id() {
-                    main.render(&device, &queue).expect("Render main gone bad");
-                } else if window_id == extra.viewport.window.id() {
-                    extra
-                        .render(&device, &queue)
-                        .expect("Render extra gone bad");
-                } else {
-                    panic!("OTHER WINDOW???");
+                if let Err(e) = app.render(window_id, gpu.context(), gpu.timer.as_ref()) {
+                    // Out of memory, the device is as good as lost
+                    eprintln!("Error rendering, rebuilding on a new device: {:#?}", e);
+                    app.rebuild_gpu = true;
+                    *control_flow = ControlFlow::Poll;
                 }
             }
 
-            Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            } => *control_flow = ControlFlow::Exit,
+            Event::LoopDestroyed => {
+                if cli.no_session {
+                    return;
+                }
+
+                session.update(&app);
+                match session.save(&cli.session) {
+                    Ok(()) => info!("Saved session to {:?}", cli.session),
+                    Err(e) => eprintln!("Error saving session: {:#?}", e),
+                }
+            }
 
             _ => {}
         }
@@ -164,7 +277,16 @@ async fn run() -> Result<()> {
 }
 
 fn main() -> Result<()> {
-    env_logger::init_from_env(env_logger::Env::default().default_filter_or("warn"));
+    let cli = Cli::parse();
+
+    match cli.log_level {
+        Some(level) => env_logger::Builder::new().filter_level(level).init(),
+        None => env_logger::init_from_env(env_logger::Env::default().default_filter_or("warn")),
+    }
+
+    if headless::requested(&cli) {
+        return pollster::block_on(headless::run(&cli));
+    }
 
-    pollster::block_on(run())
+    pollster::block_on(run(cli))
 }
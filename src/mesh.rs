@@ -0,0 +1,600 @@
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    f32::consts::TAU,
+    fs,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
+};
+use serde::{Deserialize, Serialize};
+use wgpu::{IndexFormat, PrimitiveState, PrimitiveTopology};
+
+use crate::vertex::Vertex;
+
+/// Indices into a mesh's vertices, as small as the vertex count allows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    /// 16 bit indices if every index fits.
+    pub fn new(indices: Vec<u32>) -> Self {
+        match indices.iter().all(|index| *index <= u16::MAX as u32) {
+            true => Indices::U16(indices.into_iter().map(|index| index as u16).collect()),
+            false => Indices::U32(indices),
+        }
+    }
+
+    pub fn format(&self) -> IndexFormat {
+        match self {
+            Indices::U16(_) => IndexFormat::Uint16,
+            Indices::U32(_) => IndexFormat::Uint32,
+        }
+    }
+}
+
+/// How a mesh's indices make up primitives.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Topology {
+    PointList,
+    LineList,
+    LineStrip,
+    #[default]
+    TriangleList,
+    TriangleStrip,
+}
+
+impl Topology {
+    /// The pipeline state to draw primitives of this kind with indices of `index_format`.
+    pub fn primitive_state(self, index_format: IndexFormat) -> PrimitiveState {
+        let strip = matches!(self, Topology::LineStrip | Topology::TriangleStrip);
+
+        PrimitiveState {
+            topology: self.into(),
+            // Strips must be told which index restarts them
+            strip_index_format: strip.then_some(index_format),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Topology> for PrimitiveTopology {
+    fn from(topology: Topology) -> Self {
+        match topology {
+            Topology::PointList => PrimitiveTopology::PointList,
+            Topology::LineList => PrimitiveTopology::LineList,
+            Topology::LineStrip => PrimitiveTopology::LineStrip,
+            Topology::TriangleList => PrimitiveTopology::TriangleList,
+            Topology::TriangleStrip => PrimitiveTopology::TriangleStrip,
+        }
+    }
+}
+
+/// Where the next [`Generation`] is counted from, shared by every mesh.
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Which version of a mesh's vertices and indices these are,
+/// so copies of them, such as on the GPU, can tell when they are out of date.
+///
+/// Every new or changed mesh gets a generation no other mesh had,
+/// so meshes of the same generation have the same vertices and indices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Generation(u64);
+
+impl Default for Generation {
+    fn default() -> Self {
+        Self(NEXT_GENERATION.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Vertices in NDC, and the primitives drawn between them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mesh {
+    vertices: Vec<Vertex>,
+    indices: Indices,
+    pub topology: Topology,
+
+    /// A new one whenever the vertices or indices change, also when loaded
+    #[serde(skip)]
+    generation: Generation,
+}
+
+/// Meshes are equal if they draw the same, whatever their generations.
+impl PartialEq for Mesh {
+    fn eq(&self, other: &Self) -> bool {
+        self.vertices == other.vertices
+            && self.indices == other.indices
+            && self.topology == other.topology
+    }
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, topology: Topology) -> Self {
+        Self {
+            vertices,
+            indices: Indices::new(indices),
+            topology,
+            generation: Generation::default(),
+        }
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &Indices {
+        &self.indices
+    }
+
+    pub fn generation(&self) -> Generation {
+        self.generation
+    }
+
+    /// Corners in clockwise order from the top left, with the texture stretched over them.
+    pub fn quad(top_left: [f32; 2], bottom_right: [f32; 2]) -> Self {
+        let ([left, top], [right, bottom]) = (top_left, bottom_right);
+
+        Self::new(
+            vec![
+                Vertex::new(left, top, 0.0, 0.0),
+                Vertex::new(right, top, 1.0, 0.0),
+                Vertex::new(right, bottom, 1.0, 1.0),
+                Vertex::new(left, bottom, 0.0, 1.0),
+            ],
+            vec![0, 1, 3, 3, 1, 2],
+            Topology::TriangleList,
+        )
+    }
+
+    /// A quad split into `columns` × `rows` cells, row by row from the top left,
+    /// with the texture stretched over all of them.
+    pub fn grid(top_left: [f32; 2], bottom_right: [f32; 2], columns: u32, rows: u32) -> Self {
+        let (columns, rows) = (columns.max(1), rows.max(1));
+
+        let mut vertices = vec![];
+        for row in 0..=rows {
+            let v = row as f32 / rows as f32;
+
+            for column in 0..=columns {
+                let u = column as f32 / columns as f32;
+
+                vertices.push(Vertex::new(
+                    top_left[0] + (bottom_right[0] - top_left[0]) * u,
+                    top_left[1] + (bottom_right[1] - top_left[1]) * v,
+                    u,
+                    v,
+                ));
+            }
+        }
+
+        // Two triangles per cell, wound like a quad's
+        let mut indices = vec![];
+        for row in 0..rows {
+            for column in 0..columns {
+                let top_left = row * (columns + 1) + column;
+                let top_right = top_left + 1;
+                let bottom_left = top_left + columns + 1;
+                let bottom_right = bottom_left + 1;
+
+                indices.extend([
+                    top_left,
+                    top_right,
+                    bottom_left,
+                    bottom_left,
+                    top_right,
+                    bottom_right,
+                ]);
+            }
+        }
+
+        Self::new(vertices, indices, Topology::TriangleList)
+    }
+
+    /// A fan of `segments` triangles around the centre, starting straight up.
+    /// The texture's inscribed circle is shown on it.
+    pub fn circle(centre: [f32; 2], radius: f32, segments: u32) -> Self {
+        let segments = segments.max(3);
+
+        let mut vertices = vec![Vertex::new(centre[0], centre[1], 0.5, 0.5)];
+        for segment in 0..segments {
+            // Clockwise, like the quad
+            let angle = TAU / 4.0 - TAU * segment as f32 / segments as f32;
+            let (sin, cos) = angle.sin_cos();
+
+            vertices.push(Vertex::new(
+                centre[0] + radius * cos,
+                centre[1] + radius * sin,
+                0.5 + 0.5 * cos,
+                0.5 - 0.5 * sin,
+            ));
+        }
+
+        let indices = (0..segments)
+            .flat_map(|segment| [0, segment + 1, (segment + 1) % segments + 1])
+            .collect();
+
+        Self::new(vertices, indices, Topology::TriangleList)
+    }
+
+    /// A line through the points in order, with texture coordinates running along it.
+    pub fn line_strip(points: &[[f32; 2]]) -> Self {
+        let last = points.len().saturating_sub(1).max(1) as f32;
+
+        let vertices = points
+            .iter()
+            .enumerate()
+            .map(|(index, [x, y])| Vertex::new(*x, *y, index as f32 / last, 0.5))
+            .collect();
+        let indices = (0..points.len() as u32).collect();
+
+        Self::new(vertices, indices, Topology::LineStrip)
+    }
+
+    /// Read the positions, texture coordinates and faces or lines of a Wavefront OBJ file.
+    ///
+    /// x and y are taken as NDC and z is dropped. Polygons are split into triangle fans.
+    /// Anything else in the file, such as normals, groups and materials, is ignored.
+    pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let obj = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Reading OBJ file {}", path.display()))?;
+
+        Self::parse_obj(&obj).wrap_err_with(|| format!("Parsing OBJ file {}", path.display()))
+    }
+
+    fn parse_obj(obj: &str) -> Result<Self> {
+        let mut positions = vec![];
+        let mut tex_coords = vec![];
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        let mut topology = None;
+
+        // Each distinct pair of position and texture coordinate becomes one vertex
+        let mut seen = HashMap::new();
+
+        for (number, line) in obj.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let at_line = || format!("Line {}: {:?}", number + 1, line.trim());
+
+            match words.next() {
+                Some("v") => {
+                    let [x, y] = parse_floats(&mut words).wrap_err_with(at_line)?;
+                    positions.push([x, y]);
+                }
+                Some("vt") => {
+                    let [u, v] = parse_floats(&mut words).wrap_err_with(at_line)?;
+                    // OBJ's v grows upwards, texture coordinates grow downwards
+                    tex_coords.push([u, 1.0 - v]);
+                }
+                Some(element @ ("f" | "l")) => {
+                    let element_topology = match element {
+                        "f" => Topology::TriangleList,
+                        _ => Topology::LineList,
+                    };
+                    if *topology.get_or_insert(element_topology) != element_topology {
+                        return Err(eyre!("Faces and lines can't be mixed")).wrap_err_with(at_line);
+                    }
+
+                    let mut corners = vec![];
+                    for corner in words {
+                        let index = corner_vertex(
+                            corner,
+                            &positions,
+                            &tex_coords,
+                            &mut seen,
+                            &mut vertices,
+                        )
+                        .wrap_err_with(at_line)?;
+                        corners.push(index);
+                    }
+
+                    match element_topology {
+                        Topology::TriangleList if corners.len() >= 3 => {
+                            for pair in corners[1..].windows(2) {
+                                indices.extend([corners[0], pair[0], pair[1]]);
+                            }
+                        }
+                        Topology::LineList if corners.len() >= 2 => {
+                            for pair in corners.windows(2) {
+                                indices.extend([pair[0], pair[1]]);
+                            }
+                        }
+                        _ => return Err(eyre!("Too few vertices")).wrap_err_with(at_line),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let topology = topology.ok_or_else(|| eyre!("No faces or lines"))?;
+
+        Ok(Self::new(vertices, indices, topology))
+    }
+
+    /// Move a vertex by (x, y), if there is one at `index`.
+    pub fn displace(&mut self, index: usize, x: f32, y: f32) {
+        if let Some(vertex) = self.vertex_mut(index) {
+            vertex.pos[0] += x;
+            vertex.pos[1] += y;
+        }
+    }
+
+    /// Put a vertex at (x, y), if there is one at `index`.
+    pub fn move_vertex(&mut self, index: usize, x: f32, y: f32) {
+        if let Some(vertex) = self.vertex_mut(index) {
+            vertex.pos = [x, y];
+        }
+    }
+
+    /// The vertex at `index` to change, starting a new generation if there is one.
+    fn vertex_mut(&mut self, index: usize) -> Option<&mut Vertex> {
+        let vertex = self.vertices.get_mut(index)?;
+        self.generation = Generation::default();
+
+        Some(vertex)
+    }
+}
+
+/// The first two numbers of an OBJ statement. Any more, such as z, are ignored.
+fn parse_floats<'a>(words: &mut impl Iterator<Item = &'a str>) -> Result<[f32; 2]> {
+    let mut next = || -> Result<f32> {
+        let word = words.next().ok_or_else(|| eyre!("Expected two numbers"))?;
+        word.parse()
+            .wrap_err_with(|| format!("{:?} is not a number", word))
+    };
+
+    Ok([next()?, next()?])
+}
+
+/// The vertex for a face or line corner such as `3`, `3/1` or `3/1/2`,
+/// added if this pair of position and texture coordinate is new.
+fn corner_vertex(
+    corner: &str,
+    positions: &[[f32; 2]],
+    tex_coords: &[[f32; 2]],
+    seen: &mut HashMap<(usize, Option<usize>), u32>,
+    vertices: &mut Vec<Vertex>,
+) -> Result<u32> {
+    let mut parts = corner.split('/');
+
+    let position = obj_index(parts.next().unwrap_or_default(), positions.len())?;
+    let tex_coord = match parts.next() {
+        Some("") | None => None,
+        Some(index) => Some(obj_index(index, tex_coords.len())?),
+    };
+
+    if let Some(index) = seen.get(&(position, tex_coord)) {
+        return Ok(*index);
+    }
+
+    let [x, y] = positions[position];
+    let [u, v] = tex_coord.map_or([0.0, 0.0], |tex_coord| tex_coords[tex_coord]);
+
+    let index = vertices.len() as u32;
+    vertices.push(Vertex::new(x, y, u, v));
+    seen.insert((position, tex_coord), index);
+
+    Ok(index)
+}
+
+/// OBJ indices count from 1, or back from the end of what was read so far if negative.
+fn obj_index(index: &str, count: usize) -> Result<usize> {
+    let index = index
+        .parse::<i64>()
+        .wrap_err_with(|| format!("{:?} is not an index", index))?;
+
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => count as i64 + index,
+        0 => return Err(eyre!("Indices start at 1")),
+    };
+
+    usize::try_from(resolved)
+        .ok()
+        .filter(|resolved| *resolved < count)
+        .ok_or_else(|| eyre!("Index {} is out of range, {} read so far", index, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The whole error chain of parsing `obj`, which must fail.
+    fn parse_error(obj: &str) -> String {
+        format!("{:#}", Mesh::parse_obj(obj).unwrap_err())
+    }
+
+    #[test]
+    fn obj_index_counts_from_one_or_back_from_the_end() {
+        assert_eq!(obj_index("1", 3).unwrap(), 0);
+        assert_eq!(obj_index("3", 3).unwrap(), 2);
+        assert_eq!(obj_index("-1", 3).unwrap(), 2);
+        assert_eq!(obj_index("-3", 3).unwrap(), 0);
+    }
+
+    #[test]
+    fn obj_index_rejects_zero_and_out_of_range() {
+        assert!(format!("{:#}", obj_index("0", 3).unwrap_err()).contains("start at 1"));
+        assert!(obj_index("4", 3).is_err());
+        assert!(obj_index("-4", 3).is_err());
+        assert!(obj_index("1", 0).is_err());
+        assert!(obj_index("x", 3).is_err());
+    }
+
+    #[test]
+    fn corner_vertex_reuses_pairs_already_seen() {
+        let positions = [[0.0, 0.0], [1.0, 0.0]];
+        let tex_coords = [[0.25, 0.75]];
+        let mut seen = HashMap::new();
+        let mut vertices = vec![];
+        let mut corner = |corner| {
+            corner_vertex(corner, &positions, &tex_coords, &mut seen, &mut vertices).unwrap()
+        };
+
+        assert_eq!(corner("1"), 0);
+        assert_eq!(corner("2/1"), 1);
+        assert_eq!(corner("2/1/5"), 1);
+        assert_eq!(corner("-2"), 0);
+        assert_eq!(corner("1/1"), 2);
+        assert_eq!(
+            vertices,
+            [
+                Vertex::new(0.0, 0.0, 0.0, 0.0),
+                Vertex::new(1.0, 0.0, 0.25, 0.75),
+                Vertex::new(0.0, 0.0, 0.25, 0.75),
+            ]
+        );
+    }
+
+    #[test]
+    fn corner_vertex_rejects_missing_tex_coords() {
+        let mut seen = HashMap::new();
+        let mut vertices = vec![];
+
+        assert!(corner_vertex("1/1", &[[0.0, 0.0]], &[], &mut seen, &mut vertices).is_err());
+        assert!(vertices.is_empty());
+    }
+
+    #[test]
+    fn parse_obj_splits_faces_into_fans() {
+        let mesh = Mesh::parse_obj(
+            "# A quad\n\
+             v -1 1 0\nv 1 1 0\nv 1 -1 0\nv -1 -1 0\n\
+             vt 0 1\nvt 1 0\n\
+             f 1/1 2 3/2 4 # clockwise\n",
+        )
+        .unwrap();
+
+        assert_eq!(mesh.topology, Topology::TriangleList);
+        assert_eq!(mesh.indices(), &Indices::U16(vec![0, 1, 2, 0, 2, 3]));
+        // v is flipped to grow downwards
+        assert_eq!(mesh.vertices()[0], Vertex::new(-1.0, 1.0, 0.0, 0.0));
+        assert_eq!(mesh.vertices()[2], Vertex::new(1.0, -1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn parse_obj_resolves_negative_indices() {
+        let mesh = Mesh::parse_obj("v 0 0\nv 1 0\nv 0 1\nf -3 -2 -1\n").unwrap();
+
+        assert_eq!(mesh.indices(), &Indices::U16(vec![0, 1, 2]));
+        assert_eq!(mesh.vertices()[2].pos, [0.0, 1.0]);
+    }
+
+    #[test]
+    fn parse_obj_reads_lines_as_segments() {
+        let mesh = Mesh::parse_obj("v 0 0\nv 1 0\nv 1 1\nl 1 2 3\n").unwrap();
+
+        assert_eq!(mesh.topology, Topology::LineList);
+        assert_eq!(mesh.indices(), &Indices::U16(vec![0, 1, 1, 2]));
+    }
+
+    #[test]
+    fn parse_obj_rejects_bad_indices() {
+        let triangle = "v 0 0\nv 1 0\nv 0 1\n";
+
+        assert!(parse_error(&format!("{}f 1 2 4\n", triangle)).contains("Line 4"));
+        assert!(parse_error(&format!("{}f 0 1 2\n", triangle)).contains("start at 1"));
+        assert!(parse_error(&format!("{}f -4 1 2\n", triangle)).contains("out of range"));
+    }
+
+    #[test]
+    fn parse_obj_rejects_mixed_faces_and_lines() {
+        let error = parse_error("v 0 0\nv 1 0\nv 0 1\nf 1 2 3\nl 1 2\n");
+
+        assert!(error.contains("Line 5"));
+        assert!(error.contains("can't be mixed"));
+    }
+
+    #[test]
+    fn parse_obj_rejects_too_little() {
+        assert!(parse_error("v 0 0\nv 1 0\nf 1 2\n").contains("Too few vertices"));
+        assert!(parse_error("v 0 0\nl 1\n").contains("Too few vertices"));
+        assert!(parse_error("v 0 0\n").contains("No faces or lines"));
+        assert!(parse_error("v 0\n").contains("Expected two numbers"));
+    }
+
+    #[test]
+    fn quad_corners_go_clockwise_from_the_top_left() {
+        let mesh = Mesh::quad([-1.0, 1.0], [1.0, -1.0]);
+
+        assert_eq!(
+            mesh.vertices(),
+            [
+                Vertex::new(-1.0, 1.0, 0.0, 0.0),
+                Vertex::new(1.0, 1.0, 1.0, 0.0),
+                Vertex::new(1.0, -1.0, 1.0, 1.0),
+                Vertex::new(-1.0, -1.0, 0.0, 1.0),
+            ]
+        );
+        assert_eq!(mesh.indices(), &Indices::U16(vec![0, 1, 3, 3, 1, 2]));
+    }
+
+    #[test]
+    fn grid_covers_every_cell_with_two_triangles() {
+        let mesh = Mesh::grid([-1.0, 1.0], [1.0, -1.0], 2, 1);
+
+        assert_eq!(mesh.vertices().len(), 6);
+        assert_eq!(
+            mesh.indices(),
+            &Indices::U16(vec![0, 1, 3, 3, 1, 4, 1, 2, 4, 4, 2, 5])
+        );
+        assert_eq!(mesh.vertices()[5], Vertex::new(1.0, -1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn circle_fans_out_from_the_centre() {
+        let mesh = Mesh::circle([0.0, 0.0], 1.0, 4);
+
+        assert_eq!(mesh.vertices().len(), 5);
+        assert_eq!(
+            mesh.indices(),
+            &Indices::U16(vec![0, 1, 2, 0, 2, 3, 0, 3, 4, 0, 4, 1])
+        );
+
+        // Fewer than three segments can't enclose anything
+        assert_eq!(Mesh::circle([0.0, 0.0], 1.0, 1).vertices().len(), 4);
+    }
+
+    #[test]
+    fn line_strip_runs_through_the_points_in_order() {
+        let mesh = Mesh::line_strip(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]]);
+
+        assert_eq!(mesh.topology, Topology::LineStrip);
+        assert_eq!(mesh.indices(), &Indices::U16(vec![0, 1, 2]));
+        assert_eq!(mesh.vertices()[1], Vertex::new(1.0, 0.0, 0.5, 0.5));
+    }
+
+    #[test]
+    fn indices_widen_only_when_needed() {
+        assert_eq!(Indices::new(vec![0, 65535]), Indices::U16(vec![0, 65535]));
+        assert_eq!(Indices::new(vec![0, 65536]), Indices::U32(vec![0, 65536]));
+    }
+
+    #[test]
+    fn changes_start_a_new_generation() {
+        let mut mesh = Mesh::quad([-1.0, 1.0], [1.0, -1.0]);
+        let copy = mesh.clone();
+        assert_eq!(copy.generation(), mesh.generation());
+
+        mesh.move_vertex(0, 0.5, 0.5);
+        assert_ne!(copy.generation(), mesh.generation());
+
+        // Moving back draws the same again, in a generation of its own
+        mesh.move_vertex(0, -1.0, 1.0);
+        assert_eq!(copy, mesh);
+        assert_ne!(copy.generation(), mesh.generation());
+
+        let generation = mesh.generation();
+        mesh.displace(4, 1.0, 1.0);
+        assert_eq!(generation, mesh.generation());
+    }
+}
@@ -1,3 +1,5 @@
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
 #[derive(Debug)]
 pub enum Direction {
     Up,
@@ -16,3 +18,19 @@ pub fn displace(direction: Direction, amount: f32) -> (f32, f32) {
         Direction::Right => (amount, 0.0),
     }
 }
+
+/// Cursor position in a window of `size` to NDC.
+pub fn cursor_to_ndc(size: PhysicalSize<u32>, position: PhysicalPosition<f64>) -> (f32, f32) {
+    (
+        (2.0 * position.x / size.width as f64 - 1.0) as f32,
+        (1.0 - 2.0 * position.y / size.height as f64) as f32,
+    )
+}
+
+/// NDC to a cursor position in a window of `size`.
+pub fn ndc_to_cursor(size: PhysicalSize<u32>, (x, y): (f32, f32)) -> PhysicalPosition<f64> {
+    PhysicalPosition::new(
+        (x as f64 + 1.0) / 2.0 * size.width as f64,
+        (1.0 - y as f64) / 2.0 * size.height as f64,
+    )
+}
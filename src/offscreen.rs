@@ -0,0 +1,138 @@
+use std::{num::NonZeroU32, path::Path};
+
+use color_eyre::Result;
+use image::RgbaImage;
+use wgpu::*;
+use winit::dpi::PhysicalSize;
+
+use crate::resources::{self, Tracked};
+
+/// Bytes per pixel of the formats we render to.
+const BYTES_PER_PIXEL: u32 = 4;
+
+/// A texture to render into instead of a window's surface,
+/// plus a buffer to read the result back to the CPU.
+pub struct Offscreen {
+    pub texture: Tracked<Texture>,
+    pub view: TextureView,
+    pub format: TextureFormat,
+    pub size: PhysicalSize<u32>,
+
+    buffer: Tracked<Buffer>,
+
+    /// Rows in buffer copies must be aligned to [`COPY_BYTES_PER_ROW_ALIGNMENT`]
+    padded_bytes_per_row: u32,
+}
+
+impl Offscreen {
+    pub fn new(device: &Device, size: PhysicalSize<u32>, format: TextureFormat) -> Self {
+        let texture = resources::create_texture(
+            device,
+            &TextureDescriptor {
+                label: Some("Offscreen texture"),
+                size: Extent3d {
+                    width: size.width,
+                    height: size.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            },
+        );
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        let unpadded_bytes_per_row = size.width * BYTES_PER_PIXEL;
+        let align = COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = resources::create_buffer(
+            device,
+            &BufferDescriptor {
+                label: Some("Offscreen readback buffer"),
+                size: (padded_bytes_per_row * size.height) as BufferAddress,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            },
+        );
+
+        Self {
+            texture,
+            view,
+            format,
+            size,
+            buffer,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Copy what was rendered back to the CPU.
+    /// Blocks until the GPU is done.
+    pub fn read(&self, device: &Device, queue: &Queue) -> Result<RgbaImage> {
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Offscreen readback encoder"),
+        });
+
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(self.padded_bytes_per_row),
+                    rows_per_image: NonZeroU32::new(self.size.height),
+                },
+            },
+            Extent3d {
+                width: self.size.width,
+                height: self.size.height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        queue.submit(Some(encoder.finish()));
+
+        let slice = self.buffer.slice(..);
+        let mapping = slice.map_async(MapMode::Read);
+        device.poll(Maintain::Wait);
+        pollster::block_on(mapping)?;
+
+        let unpadded_bytes_per_row = (self.size.width * BYTES_PER_PIXEL) as usize;
+        let mut pixels = Vec::with_capacity(unpadded_bytes_per_row * self.size.height as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row]);
+            }
+        }
+        self.buffer.unmap();
+
+        if matches!(
+            self.format,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_mut(BYTES_PER_PIXEL as usize) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        let image = RgbaImage::from_raw(self.size.width, self.size.height, pixels)
+            .expect("Buffer holds exactly one image");
+
+        Ok(image)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, device: &Device, queue: &Queue, path: P) -> Result<()> {
+        self.read(device, queue)?
+            .save_with_format(path, image::ImageFormat::Png)?;
+
+        Ok(())
+    }
+}
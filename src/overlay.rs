@@ -0,0 +1,296 @@
+use std::marker::PhantomData;
+
+use color_eyre::Result;
+use wgpu::*;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+use crate::{
+    gpu_mesh::GpuMesh,
+    misc,
+    pipeline::{BindLayout, PipelineBuilder, PipelineCache, Shader, SharedPipeline},
+    texture_image::{self, TextureImage},
+    vertex::Vertex,
+};
+
+/// How close the cursor must be to a handle to grab it, in logical pixels.
+const GRAB_DISTANCE: f64 = 10.0;
+
+/// Flat colours an overlay is drawn with, one texel each in its palette texture.
+pub trait Palette: Copy {
+    /// Rgba colours, in the order of [`Palette::index`].
+    const COLOURS: &'static [[u8; 4]];
+
+    fn index(self) -> usize;
+
+    /// Texture coordinates of this colour in the palette texture.
+    fn tc(self) -> (f32, f32) {
+        (
+            (self.index() as f32 + 0.5) / Self::COLOURS.len() as f32,
+            0.5,
+        )
+    }
+}
+
+/// The handle nearest to the cursor, if it is within reach.
+///
+/// Handles are in NDC, but distances are measured in physical pixels,
+/// so the reach is the same across and along windows that aren't square.
+pub fn nearest_handle(
+    handles: impl IntoIterator<Item = (f32, f32)>,
+    cursor: PhysicalPosition<f64>,
+    size: PhysicalSize<u32>,
+    scale_factor: f64,
+) -> Option<usize> {
+    handles
+        .into_iter()
+        .map(|handle| {
+            let handle = misc::ndc_to_cursor(size, handle);
+            (handle.x - cursor.x).hypot(handle.y - cursor.y)
+        })
+        .enumerate()
+        .filter(|(_, distance)| *distance <= GRAB_DISTANCE * scale_factor)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+}
+
+/// Flat coloured shapes to draw into a window in one go, in the order they were added.
+///
+/// Positions are in NDC, widths and sizes in logical pixels.
+pub struct OverlayBatch<P> {
+    /// One logical pixel, in NDC
+    pixel: (f32, f32),
+
+    /// What the window is cleared to first, if anything
+    background: Option<Color>,
+
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    palette: PhantomData<P>,
+}
+
+impl<P: Palette> OverlayBatch<P> {
+    pub fn new(size: PhysicalSize<u32>, scale_factor: f64) -> Self {
+        let scale_factor = scale_factor as f32;
+
+        Self {
+            pixel: (
+                2.0 * scale_factor / size.width.max(1) as f32,
+                2.0 * scale_factor / size.height.max(1) as f32,
+            ),
+            background: None,
+            vertices: vec![],
+            indices: vec![],
+            palette: PhantomData,
+        }
+    }
+
+    /// Clear the window to `colour` before drawing the shapes.
+    pub fn clear(&mut self, colour: Color) {
+        self.background = Some(colour);
+    }
+
+    /// A quad through corners in the order top left, top right, bottom right, bottom left.
+    pub fn quad(&mut self, corners: [(f32, f32); 4], colour: P) {
+        let (u, v) = colour.tc();
+
+        let first = self.vertices.len() as u32;
+        self.vertices
+            .extend(corners.iter().map(|(x, y)| Vertex::new(*x, *y, u, v)));
+        self.indices
+            .extend([0, 1, 3, 3, 1, 2].iter().map(|i| first + i));
+    }
+
+    /// A square around `centre`, reaching `half` logical pixels to each side.
+    pub fn square(&mut self, (x, y): (f32, f32), half: f32, colour: P) {
+        let (w, h) = (half * self.pixel.0, half * self.pixel.1);
+
+        self.quad(
+            [
+                (x - w, y + h),
+                (x + w, y + h),
+                (x + w, y - h),
+                (x - w, y - h),
+            ],
+            colour,
+        );
+    }
+
+    /// A line `width` logical pixels wide.
+    pub fn line(&mut self, (x0, y0): (f32, f32), (x1, y1): (f32, f32), width: f32, colour: P) {
+        // Widened perpendicular to the line in pixels, as NDC stretch with the window
+        let pixel = self.pixel;
+        let (dx, dy) = ((x1 - x0) / pixel.0, (y1 - y0) / pixel.1);
+        let length = dx.hypot(dy).max(f32::EPSILON);
+        let (nx, ny) = (
+            -dy / length * width / 2.0 * pixel.0,
+            dx / length * width / 2.0 * pixel.1,
+        );
+
+        self.quad(
+            [
+                (x0 + nx, y0 + ny),
+                (x1 + nx, y1 + ny),
+                (x1 - nx, y1 - ny),
+                (x0 - nx, y0 - ny),
+            ],
+            colour,
+        );
+    }
+
+    /// Lines `width` logical pixels wide between consecutive points.
+    pub fn line_strip(&mut self, points: &[(f32, f32)], width: f32, colour: P) {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], width, colour);
+        }
+    }
+}
+
+fn bind_group_layout(device: &Device) -> BindLayout {
+    BindLayout::new(
+        device,
+        "Overlay bind group layout",
+        &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler {
+                    filtering: true,
+                    comparison: false,
+                },
+                count: None,
+            },
+        ],
+    )
+}
+
+fn render_pipeline(
+    device: &Device,
+    pipelines: &PipelineCache,
+    layout: &BindLayout,
+    format: &TextureFormat,
+) -> Result<SharedPipeline> {
+    PipelineBuilder::new(
+        "Overlay render pipeline",
+        Shader::Static {
+            label: "Overlay shader",
+            source: include_str!("shaders/overlay.wgsl"),
+        },
+        *format,
+    )
+    .layout("Overlay pipeline layout", &[layout])
+    .vertex::<Vertex>()
+    .build(device, pipelines)
+}
+
+/// Draws [`OverlayBatch`]es in the colours of palette `P`.
+pub struct OverlayRenderer<P> {
+    label: &'static str,
+    render_pipeline: SharedPipeline,
+    bind_group_layout: BindLayout,
+    bind_group: BindGroup,
+    mesh: GpuMesh<Vertex>,
+
+    #[allow(dead_code)]
+    palette_image: TextureImage,
+    palette: PhantomData<P>,
+}
+
+impl<P: Palette> OverlayRenderer<P> {
+    pub fn new(
+        label: &'static str,
+        device: &Device,
+        queue: &Queue,
+        pipelines: &PipelineCache,
+        format: &TextureFormat,
+    ) -> Result<Self> {
+        let palette_image = TextureImage::new(
+            &format!("{} palette", label),
+            device,
+            P::COLOURS.len(),
+            1,
+            &P::COLOURS.concat(),
+        )?;
+        palette_image.write(queue);
+
+        let bind_group_layout = bind_group_layout(device);
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some(&format!("{} bind group", label)),
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&palette_image.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&texture_image::nearest_sampler(device)),
+                },
+            ],
+        });
+
+        Ok(Self {
+            label,
+            render_pipeline: render_pipeline(device, pipelines, &bind_group_layout, format)?,
+            bind_group_layout,
+            bind_group,
+            mesh: GpuMesh::new(device, label),
+            palette_image,
+            palette: PhantomData,
+        })
+    }
+
+    pub fn set_texture_format(
+        &mut self,
+        device: &Device,
+        pipelines: &PipelineCache,
+        format: &TextureFormat,
+    ) -> Result<()> {
+        self.render_pipeline = render_pipeline(device, pipelines, &self.bind_group_layout, format)?;
+
+        Ok(())
+    }
+
+    pub fn render(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        texture_view: &TextureView,
+        batch: &OverlayBatch<P>,
+    ) {
+        self.mesh
+            .upload(device, queue, &batch.vertices, &batch.indices);
+
+        encoder.push_debug_group(self.label);
+        let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(self.label),
+            color_attachments: &[RenderPassColorAttachment {
+                view: texture_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: batch.background.map_or(LoadOp::Load, LoadOp::Clear),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.insert_debug_marker("Draw overlay shapes");
+        self.mesh.draw(&mut rpass);
+
+        drop(rpass);
+        encoder.pop_debug_group();
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    hash::{Hash, Hasher},
+    mem,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use color_eyre::Result;
+use wgpu::*;
+
+use crate::{
+    mesh::Topology,
+    resources::{self, Tracked},
+    vertex_layout::VertexLayout,
+};
+
+/// Where a pipeline's WGSL comes from.
+/// Shaders are told apart by their source alone, not their label.
+#[derive(Debug, Clone)]
+pub enum Shader {
+    /// Built into the binary
+    Static {
+        label: &'static str,
+        source: &'static str,
+    },
+    /// Read when a pipeline is built, and changed by hot reloads
+    File(PathBuf),
+}
+
+impl PartialEq for Shader {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Shader::Static { source: a, .. }, Shader::Static { source: b, .. }) => a == b,
+            (Shader::File(a), Shader::File(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Shader {}
+
+impl Hash for Shader {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Shader::Static { source, .. } => source.hash(state),
+            Shader::File(path) => path.hash(state),
+        }
+    }
+}
+
+impl Shader {
+    fn create_module(&self, device: &Device) -> Result<ShaderModule> {
+        let (label, wgsl) = match self {
+            Shader::Static { label, source } => (label.to_string(), Cow::Borrowed(*source)),
+            Shader::File(path) => {
+                let wgsl = fs::read_to_string(path)?;
+                // wgpu panics on invalid shaders, naga says what's wrong
+                naga::front::wgsl::parse_str(&wgsl)?;
+
+                (path.display().to_string(), Cow::Owned(wgsl))
+            }
+        };
+
+        Ok(device.create_shader_module(&ShaderModuleDescriptor {
+            label: Some(&label),
+            source: ShaderSource::Wgsl(wgsl),
+        }))
+    }
+}
+
+/// A pipeline shared by everything built with the same settings.
+pub type SharedPipeline = Arc<Tracked<RenderPipeline>>;
+
+/// A bind group layout, with the entries it was made from to tell it apart from others.
+#[derive(Debug)]
+pub struct BindLayout {
+    layout: BindGroupLayout,
+    entries: Vec<BindGroupLayoutEntry>,
+}
+
+impl BindLayout {
+    pub fn new(device: &Device, label: &str, entries: &[BindGroupLayoutEntry]) -> Self {
+        Self {
+            layout: device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries,
+            }),
+            entries: entries.to_vec(),
+        }
+    }
+}
+
+impl Deref for BindLayout {
+    type Target = BindGroupLayout;
+
+    fn deref(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+}
+
+/// Everything a pipeline is built from, apart from debug labels.
+#[derive(PartialEq, Eq, Hash)]
+struct PipelineKey {
+    shader: Shader,
+    bind_group_layouts: Vec<Vec<BindGroupLayoutEntry>>,
+    vertex_entry_point: String,
+    fragment_entry_point: String,
+    vertex_layouts: Vec<VertexBufferLayout<'static>>,
+    blend: Option<BlendState>,
+    primitive: PrimitiveState,
+    format: TextureFormat,
+}
+
+/// The pipelines built on one device, by everything they were built from.
+/// Kept next to the device, so they go when it does.
+#[derive(Default)]
+pub struct PipelineCache {
+    entries: RefCell<HashMap<PipelineKey, SharedPipeline>>,
+}
+
+impl PipelineCache {
+    /// Forget the pipelines built from a shader file, so they're built again from what it holds now.
+    /// Whoever still holds one keeps drawing with it until they build a new one.
+    pub fn invalidate(&self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        self.entries
+            .borrow_mut()
+            .retain(|key, _| match &key.shader {
+                Shader::File(shader) => {
+                    fs::canonicalize(shader).unwrap_or_else(|_| shader.clone()) != path
+                }
+                Shader::Static { .. } => true,
+            });
+    }
+}
+
+/// A render pipeline drawing into one colour target, built once per distinct setup.
+pub struct PipelineBuilder<'a> {
+    label: &'a str,
+    shader: Shader,
+    layout_label: &'a str,
+    bind_group_layouts: Vec<&'a BindLayout>,
+    vertex_entry_point: &'a str,
+    fragment_entry_point: &'a str,
+    vertex_layouts: Vec<VertexBufferLayout<'static>>,
+    blend: Option<BlendState>,
+    primitive: PrimitiveState,
+    format: TextureFormat,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    /// Triangle lists from `vs_main` and `fs_main`, replacing what's in the target.
+    pub fn new(label: &'a str, shader: Shader, format: TextureFormat) -> Self {
+        Self {
+            label,
+            shader,
+            layout_label: label,
+            bind_group_layouts: vec![],
+            vertex_entry_point: "vs_main",
+            fragment_entry_point: "fs_main",
+            vertex_layouts: vec![],
+            blend: None,
+            primitive: PrimitiveState::default(),
+            format,
+        }
+    }
+
+    pub fn layout(mut self, label: &'a str, bind_group_layouts: &[&'a BindLayout]) -> Self {
+        self.layout_label = label;
+        self.bind_group_layouts = bind_group_layouts.to_vec();
+        self
+    }
+
+    #[cfg_attr(not(feature = "inspector"), allow(dead_code))]
+    pub fn entry_points(mut self, vertex: &'a str, fragment: &'a str) -> Self {
+        self.vertex_entry_point = vertex;
+        self.fragment_entry_point = fragment;
+        self
+    }
+
+    /// Add a vertex buffer holding `V`s, at the next buffer slot.
+    pub fn vertex<V: VertexLayout>(mut self) -> Self {
+        self.vertex_layouts.push(V::layout());
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendState) -> Self {
+        self.blend = Some(blend);
+        self
+    }
+
+    /// Strips restart at the largest `index_format` index.
+    pub fn topology(mut self, topology: Topology, index_format: IndexFormat) -> Self {
+        self.primitive = topology.primitive_state(index_format);
+        self
+    }
+
+    fn key(&self) -> PipelineKey {
+        PipelineKey {
+            shader: self.shader.clone(),
+            bind_group_layouts: self
+                .bind_group_layouts
+                .iter()
+                .map(|layout| layout.entries.clone())
+                .collect(),
+            vertex_entry_point: self.vertex_entry_point.to_string(),
+            fragment_entry_point: self.fragment_entry_point.to_string(),
+            vertex_layouts: self.vertex_layouts.clone(),
+            blend: self.blend,
+            primitive: self.primitive,
+            format: self.format,
+        }
+    }
+
+    /// The pipeline from `cache`, or a new one if nothing was built like it yet.
+    /// Pipelines differing only in their labels are shared, under the first one's labels.
+    pub fn build(self, device: &Device, cache: &PipelineCache) -> Result<SharedPipeline> {
+        let key = self.key();
+        if let Some(pipeline) = cache.entries.borrow().get(&key) {
+            return Ok(pipeline.clone());
+        }
+
+        let shader = self.shader.create_module(device)?;
+        let bind_group_layouts = self
+            .bind_group_layouts
+            .iter()
+            .map(|layout| &layout.layout)
+            .collect::<Vec<_>>();
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(self.layout_label),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = Arc::new(resources::create_render_pipeline(
+            device,
+            &RenderPipelineDescriptor {
+                label: Some(self.label),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: self.vertex_entry_point,
+                    buffers: &self.vertex_layouts,
+                },
+                primitive: self.primitive,
+                depth_stencil: None,
+                fragment: Some(FragmentState {
+                    module: &shader,
+                    entry_point: self.fragment_entry_point,
+                    targets: &[ColorTargetState {
+                        format: self.format,
+                        blend: self.blend,
+                        write_mask: ColorWrites::ALL,
+                    }],
+                }),
+                multisample: MultisampleState::default(),
+            },
+        ));
+
+        cache.entries.borrow_mut().insert(key, pipeline.clone());
+
+        Ok(pipeline)
+    }
+}
@@ -0,0 +1,184 @@
+use std::path::Path;
+
+use color_eyre::Result;
+
+use crate::camera::Camera;
+
+/// Terrain heights read from a height map image, in [0, 1].
+pub struct HeightMap {
+    width: u32,
+    height: u32,
+    heights: Vec<f32>,
+}
+
+impl HeightMap {
+    pub fn new_from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let image = image::open(path)?.to_luma8();
+        let (width, height) = image.dimensions();
+        let heights = image
+            .pixels()
+            .map(|pixel| pixel[0] as f32 / 255.0)
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            heights,
+        })
+    }
+
+    /// The height at camera coordinates, nearest texel.
+    /// `None` off the map.
+    pub fn at(&self, x: f32, y: f32) -> Option<f32> {
+        // Same orientation as the maps in the extra window: y = 1.0 is the top row
+        let u = (x + 1.0) / 2.0;
+        let v = (1.0 - y) / 2.0;
+
+        if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+            return None;
+        }
+
+        let column = (u * self.width as f32) as u32;
+        let row = (v * self.height as f32) as u32;
+
+        self.heights
+            .get((row * self.width + column) as usize)
+            .copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfileSample {
+    /// From the camera, in camera coordinates
+    pub distance: f32,
+
+    /// Terrain height
+    pub terrain: f32,
+
+    /// Whether the camera sees this terrain, rather than looking past it
+    /// or something nearer being in the way
+    pub visible: bool,
+}
+
+/// A side-on cross-section of the terrain along the camera's view direction,
+/// from the camera out to its viewing distance.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Profile {
+    pub samples: Vec<ProfileSample>,
+}
+
+impl Profile {
+    /// Walk away from the camera in `samples` even steps, stopping at the edge of the map.
+    ///
+    /// Terrain is visible when the ray from the camera to it is at least as steep
+    /// as the ray to any terrain in front of it,
+    /// which is what a renderer drawing front to back ends up showing,
+    /// and within the field of view around the camera's pitch, taken to be as tall as it is wide.
+    pub fn new(height_map: &HeightMap, camera: &Camera, samples: usize) -> Self {
+        let [dx, dy] = camera.viewing_dir.as_array();
+        let step = camera.viewing_distance / samples as f32;
+        let in_view = (camera.pitch - camera.fov / 2.0)..=(camera.pitch + camera.fov / 2.0);
+
+        let mut steepest = f32::NEG_INFINITY;
+
+        let samples = (1..=samples)
+            .map(|sample| sample as f32 * step)
+            .map_while(|distance| {
+                let terrain = height_map.at(camera.x + dx * distance, camera.y + dy * distance)?;
+
+                let slope = (terrain - camera.height) / distance;
+                let visible = slope >= steepest && in_view.contains(&slope.atan());
+                steepest = steepest.max(slope);
+
+                Some(ProfileSample {
+                    distance,
+                    terrain,
+                    visible,
+                })
+            })
+            .collect();
+
+        Self { samples }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts;
+
+    use super::*;
+    use crate::{camera::FOV_MAX, vec::Vec2};
+
+    /// One row of terrain, west to east.
+    fn row(heights: &[f32]) -> HeightMap {
+        HeightMap {
+            width: heights.len() as u32,
+            height: 1,
+            heights: heights.to_vec(),
+        }
+    }
+
+    /// On the west edge of the map, looking east over `distance`.
+    fn camera(height: f32, distance: f32, fov: f32, pitch: f32) -> Camera {
+        Camera {
+            x: -1.0,
+            y: 0.0,
+            height,
+            viewing_distance: distance,
+            viewing_angle: 0.0,
+            viewing_dir: Vec2::new_from_angle(0.0),
+            fov,
+            pitch,
+            ..Default::default()
+        }
+    }
+
+    fn visible(profile: &Profile) -> Vec<bool> {
+        profile
+            .samples
+            .iter()
+            .map(|sample| sample.visible)
+            .collect()
+    }
+
+    #[test]
+    fn heights_are_read_with_the_top_row_at_positive_y() {
+        let map = HeightMap {
+            width: 2,
+            height: 2,
+            heights: vec![0.1, 0.2, 0.3, 0.4],
+        };
+
+        assert_eq!(map.at(-0.5, 0.5), Some(0.1));
+        assert_eq!(map.at(0.5, 0.5), Some(0.2));
+        assert_eq!(map.at(-0.5, -0.5), Some(0.3));
+        assert_eq!(map.at(1.5, 0.0), None);
+    }
+
+    #[test]
+    fn samples_stop_at_the_edge_of_the_map() {
+        let profile = Profile::new(&row(&[0.0; 4]), &camera(0.5, 4.0, FOV_MAX, 0.0), 8);
+
+        assert_eq!(profile.samples.len(), 3);
+    }
+
+    #[test]
+    fn a_ridge_hides_the_terrain_behind_it() {
+        // Samples at columns 1 to 4, the first of them a ridge above the camera
+        let map = row(&[0.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        let profile = Profile::new(&map, &camera(0.4, 1.0, FOV_MAX, 0.0), 4);
+
+        assert_eq!(visible(&profile), [true, false, false, false]);
+    }
+
+    #[test]
+    fn ground_below_the_field_of_view_is_not_seen_until_looking_down() {
+        let map = row(&[0.0; 8]);
+
+        let level = Profile::new(&map, &camera(0.6, 0.25, consts::FRAC_PI_4, 0.0), 4);
+        assert_eq!(visible(&level), [false; 4]);
+
+        let down = Profile::new(&map, &camera(0.6, 0.25, consts::FRAC_PI_4, -1.2), 4);
+        assert!(visible(&down).iter().any(|visible| *visible));
+    }
+}
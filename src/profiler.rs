@@ -0,0 +1,314 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    fs,
+    path::Path,
+    time::Instant,
+};
+
+use color_eyre::Result;
+use wgpu::{
+    Buffer, BufferAddress, BufferDescriptor, BufferUsages, CommandEncoder, Device, Features,
+    Maintain, MapMode, QuerySet, QuerySetDescriptor, QueryType, Queue, QUERY_SIZE,
+};
+
+use crate::resources::{self, Tracked};
+
+/// How many frames the figures are taken over.
+const FRAMES: usize = 240;
+
+/// How many passes can be timed on the GPU per frame.
+const MAX_SCOPES: u32 = 32;
+
+/// What the CPU spends a frame on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Handling window and device events
+    Events,
+    /// Simulating the scene
+    Update,
+    /// Waiting for a surface texture to draw into
+    Acquire,
+    /// Recording commands
+    Encode,
+    /// Handing commands to the GPU
+    Submit,
+    /// Presenting surface textures
+    Present,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub min: f32,
+    pub avg: f32,
+    pub p99: f32,
+}
+
+/// The last [`FRAMES`] values of something, in milliseconds.
+#[derive(Debug, Default)]
+struct Samples {
+    values: VecDeque<f32>,
+}
+
+impl Samples {
+    fn push(&mut self, ms: f32) {
+        if self.values.len() == FRAMES {
+            self.values.pop_front();
+        }
+        self.values.push_back(ms);
+    }
+
+    fn stats(&self) -> Option<Stats> {
+        if self.values.is_empty() {
+            return None;
+        }
+
+        let mut sorted = self.values.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(f32::total_cmp);
+
+        // Nearest rank
+        let p99 = ((sorted.len() as f32 * 0.99).ceil() as usize).clamp(1, sorted.len()) - 1;
+
+        Some(Stats {
+            min: sorted[0],
+            avg: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            p99: sorted[p99],
+        })
+    }
+}
+
+/// Rolling min/avg/p99 frame timings, per CPU phase and per GPU pass.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    /// Whether the figures are shown in the window titles.
+    /// GPU passes are only timed while shown, as reading the timings back waits for the GPU.
+    pub enabled: bool,
+
+    cpu: BTreeMap<Phase, Samples>,
+    cpu_total: Samples,
+    gpu: BTreeMap<&'static str, Samples>,
+    gpu_total: Samples,
+
+    /// Time spent per phase in the frame so far, milliseconds
+    frame: BTreeMap<Phase, f32>,
+}
+
+impl Profiler {
+    /// Add the time since `start` to a phase of the current frame.
+    pub fn record(&mut self, phase: Phase, start: Instant) {
+        *self.frame.entry(phase).or_default() += start.elapsed().as_secs_f32() * 1000.0;
+    }
+
+    /// Take in the current frame's CPU time. Phases it didn't go through count as zero.
+    pub fn end_frame(&mut self) {
+        let frame = std::mem::take(&mut self.frame);
+
+        for phase in [
+            Phase::Events,
+            Phase::Update,
+            Phase::Acquire,
+            Phase::Encode,
+            Phase::Submit,
+            Phase::Present,
+        ] {
+            let ms = frame.get(&phase).copied().unwrap_or_default();
+            self.cpu.entry(phase).or_default().push(ms);
+        }
+        self.cpu_total.push(frame.values().sum());
+    }
+
+    /// Take in one frame's GPU time, per pass.
+    fn push_gpu(&mut self, passes: BTreeMap<&'static str, f32>) {
+        self.gpu_total.push(passes.values().sum());
+
+        for (label, ms) in passes {
+            self.gpu.entry(label).or_default().push(ms);
+        }
+    }
+
+    /// Totals for the window titles.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+
+        for (name, stats) in [
+            ("CPU", self.cpu_total.stats()),
+            ("GPU", self.gpu_total.stats()),
+        ] {
+            if let Some(stats) = stats {
+                let _ = write!(
+                    summary,
+                    "{} {:.2}/{:.2}/{:.2} ms, ",
+                    name, stats.min, stats.avg, stats.p99
+                );
+            }
+        }
+
+        match summary.is_empty() {
+            true => "no frames yet".to_string(),
+            false => format!("{}min/avg/p99", summary),
+        }
+    }
+
+    pub fn save_csv<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut csv = String::from("kind,name,min_ms,avg_ms,p99_ms,frames\n");
+
+        let cpu = self
+            .cpu
+            .iter()
+            .map(|(phase, samples)| ("cpu", format!("{:?}", phase), samples))
+            .chain(std::iter::once((
+                "cpu",
+                "Total".to_string(),
+                &self.cpu_total,
+            )));
+        let gpu = self
+            .gpu
+            .iter()
+            .map(|(label, samples)| ("gpu", label.to_string(), samples))
+            .chain(std::iter::once((
+                "gpu",
+                "Total".to_string(),
+                &self.gpu_total,
+            )));
+
+        for (kind, name, samples) in cpu.chain(gpu) {
+            if let Some(stats) = samples.stats() {
+                writeln!(
+                    csv,
+                    "{},{},{:.4},{:.4},{:.4},{}",
+                    kind,
+                    name,
+                    stats.min,
+                    stats.avg,
+                    stats.p99,
+                    samples.values.len()
+                )?;
+            }
+        }
+
+        fs::write(path, csv)?;
+
+        Ok(())
+    }
+}
+
+/// Timestamps written around render passes, when the adapter supports them.
+pub struct GpuTimer {
+    query_set: Tracked<QuerySet>,
+    buffer: Tracked<Buffer>,
+
+    /// Nanoseconds per timestamp tick
+    period: f32,
+
+    /// The label of each pair of timestamps written this frame
+    scopes: RefCell<Vec<&'static str>>,
+}
+
+impl GpuTimer {
+    /// The features a device needs for this, if the adapter has them.
+    pub fn features(adapter_features: Features) -> Features {
+        adapter_features & Features::TIMESTAMP_QUERY
+    }
+
+    /// `None` if the device can't write timestamps.
+    pub fn new(device: &Device, queue: &Queue) -> Option<Self> {
+        if !device.features().contains(Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = resources::create_query_set(
+            device,
+            &QuerySetDescriptor {
+                label: Some("Pass timestamps"),
+                ty: QueryType::Timestamp,
+                count: MAX_SCOPES * 2,
+            },
+        );
+        let buffer = resources::create_buffer(
+            device,
+            &BufferDescriptor {
+                label: Some("Pass timestamps"),
+                size: (MAX_SCOPES * 2 * QUERY_SIZE) as BufferAddress,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            },
+        );
+
+        Some(Self {
+            query_set,
+            buffer,
+            period: queue.get_timestamp_period(),
+            scopes: RefCell::new(vec![]),
+        })
+    }
+
+    /// Time the passes recorded by `encode`.
+    /// Passes beyond [`MAX_SCOPES`] in a frame are recorded without timing them.
+    pub fn scope(
+        timer: Option<&Self>,
+        encoder: &mut CommandEncoder,
+        label: &'static str,
+        encode: impl FnOnce(&mut CommandEncoder),
+    ) {
+        let timer = match timer {
+            Some(timer) if timer.scopes.borrow().len() < MAX_SCOPES as usize => timer,
+            _ => return encode(encoder),
+        };
+
+        let first = {
+            let mut scopes = timer.scopes.borrow_mut();
+            scopes.push(label);
+            (scopes.len() as u32 - 1) * 2
+        };
+
+        encoder.write_timestamp(&timer.query_set, first);
+        encode(encoder);
+        encoder.write_timestamp(&timer.query_set, first + 1);
+    }
+
+    /// Copy the timestamps written so far this frame to where [`GpuTimer::collect`] reads them.
+    /// Call last in every encoder the timer was used with.
+    pub fn resolve(timer: Option<&Self>, encoder: &mut CommandEncoder) {
+        if let Some(timer) = timer {
+            let queries = timer.scopes.borrow().len() as u32 * 2;
+
+            if queries > 0 {
+                encoder.resolve_query_set(&timer.query_set, 0..queries, &timer.buffer, 0);
+            }
+        }
+    }
+
+    /// Read back the frame's timings into the profiler and start over.
+    /// Waits for the GPU to finish the frame.
+    pub fn collect(&self, device: &Device, profiler: &mut Profiler) {
+        let scopes = std::mem::take(&mut *self.scopes.borrow_mut());
+        if scopes.is_empty() {
+            return;
+        }
+
+        let slice = self
+            .buffer
+            .slice(..(scopes.len() as u32 * 2 * QUERY_SIZE) as BufferAddress);
+        let mapping = slice.map_async(MapMode::Read);
+        device.poll(Maintain::Wait);
+
+        if pollster::block_on(mapping).is_err() {
+            return;
+        }
+
+        let mut passes = BTreeMap::new();
+        {
+            let data = slice.get_mapped_range();
+            let ticks: &[u64] = bytemuck::cast_slice(&data);
+
+            for (scope, label) in scopes.iter().enumerate() {
+                let elapsed = ticks[scope * 2 + 1].saturating_sub(ticks[scope * 2]);
+                *passes.entry(*label).or_default() += elapsed as f32 * self.period / 1_000_000.0;
+            }
+        }
+        self.buffer.unmap();
+
+        profiler.push_gpu(passes);
+    }
+}
@@ -1,25 +1,45 @@
-use std::mem;
+use std::{f32::consts::FRAC_PI_3, mem};
 
 use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
 use wgpu::{
     BindGroupLayoutEntry, BindingType, BufferAddress, BufferBindingType, BufferDescriptor,
     BufferSize, BufferUsages, ShaderStages,
 };
 
 #[repr(C)]
-#[derive(Clone, Copy, Pod, Zeroable)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
 pub struct RadarUniform {
     /// The direction the radar is looking,
     /// (x, y) in ranges [-1, 1]
     pub view_dir: [f32; 2],
 
-    /// The position of the radar,
-    /// (x, y) in ranges [-1, 1]
+    /// The position of the radar in physical pixels from the window's top left corner,
+    /// as fragment positions are
     pub position: [f32; 2],
 
     /// The field of view of the radar in radians
     // pub fov: f32,
     pub fov: [f32; 2],
+
+    /// How far the camera looks up or down in radians.
+    /// Looking away from the horizon, the radar reaches less far over the ground.
+    pub pitch: f32,
+
+    #[serde(skip)]
+    _padding: f32,
+}
+
+impl Default for RadarUniform {
+    fn default() -> Self {
+        Self {
+            fov: [FRAC_PI_3, 0.0],
+            view_dir: [0.0, 1.0],
+            position: [0.0, 0.0],
+            pitch: 0.0,
+            _padding: 0.0,
+        }
+    }
 }
 
 impl RadarUniform {
@@ -40,7 +60,7 @@ impl RadarUniform {
         }
     }
 
-    pub fn buffer_descriptor(&self) -> BufferDescriptor {
+    pub fn buffer_descriptor(&self) -> BufferDescriptor<'_> {
         BufferDescriptor {
             label: Some("Radar"),
             size: self.size(),
@@ -0,0 +1,287 @@
+use std::{collections::VecDeque, fs, path::Path, time::Instant};
+
+use color_eyre::{eyre::eyre, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{DeviceEvent, ElementState, KeyboardInput, MouseButton, MouseScrollDelta, WindowEvent},
+};
+
+use crate::{camera_path::CameraPath, misc, scene::SceneSnapshot};
+
+/// Bumped whenever the file layout changes in an incompatible way.
+const VERSION: u32 = 4;
+
+/// Where an input event was received.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Target {
+    Main,
+    Extra,
+    Profile,
+
+    /// Not tied to a window, e.g. raw mouse motion
+    Device,
+}
+
+/// The subset of winit input events the app reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    Key {
+        key: winit::event::VirtualKeyCode,
+        state: ElementState,
+    },
+    CursorMoved(PhysicalPosition<f64>),
+    CursorLeft,
+    MouseButton {
+        button: MouseButton,
+        state: ElementState,
+    },
+    MouseWheel(MouseScrollDelta),
+    MouseMotion((f64, f64)),
+    FocusGained,
+    FocusLost,
+}
+
+impl InputEvent {
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        let event = match *event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => InputEvent::Key { key, state },
+            WindowEvent::CursorMoved { position, .. } => InputEvent::CursorMoved(position),
+            WindowEvent::CursorLeft { .. } => InputEvent::CursorLeft,
+            WindowEvent::MouseInput { state, button, .. } => {
+                InputEvent::MouseButton { button, state }
+            }
+            WindowEvent::MouseWheel { delta, .. } => InputEvent::MouseWheel(delta),
+            WindowEvent::Focused(true) => InputEvent::FocusGained,
+            WindowEvent::Focused(false) => InputEvent::FocusLost,
+            _ => return None,
+        };
+
+        Some(event)
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<Self> {
+        match *event {
+            DeviceEvent::MouseMotion { delta } => Some(InputEvent::MouseMotion(delta)),
+            _ => None,
+        }
+    }
+}
+
+/// An input event as recorded. Cursor positions are kept in NDC,
+/// so they land on the same spot when replayed into a window of another size.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RecordedInput {
+    CursorMoved((f32, f32)),
+    Other(InputEvent),
+}
+
+impl RecordedInput {
+    /// `size` is that of the window the event was received in.
+    pub fn new(event: InputEvent, size: PhysicalSize<u32>) -> Self {
+        match event {
+            InputEvent::CursorMoved(position) => {
+                RecordedInput::CursorMoved(misc::cursor_to_ndc(size, position))
+            }
+            event => RecordedInput::Other(event),
+        }
+    }
+
+    /// The event as received by a window of `size`.
+    pub fn event(self, size: PhysicalSize<u32>) -> InputEvent {
+        match self {
+            RecordedInput::CursorMoved(ndc) => {
+                InputEvent::CursorMoved(misc::ndc_to_cursor(size, ndc))
+            }
+            RecordedInput::Other(event) => event,
+        }
+    }
+}
+
+/// What input depends on besides the scene snapshot, restored before replaying.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedState {
+    pub camera_path: CameraPath,
+    pub selected_keyframe: Option<usize>,
+
+    /// How far along the camera path the camera was flying, if it was
+    pub path_distance: Option<f32>,
+
+    pub editing: bool,
+    pub snap: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Recorded {
+    Input {
+        /// Seconds since the recording started
+        time: f64,
+        target: Target,
+        event: RecordedInput,
+    },
+
+    /// The scene was edited other than through input events, e.g. in the inspector.
+    Edit {
+        /// Seconds since the recording started
+        time: f64,
+        /// The scene after the edit
+        scene: SceneSnapshot,
+    },
+
+    /// A frame was simulated after the preceding inputs.
+    Frame {
+        /// Seconds since the recording started
+        time: f64,
+        /// The delta time the frame used
+        delta: f32,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+
+    /// State when the recording started, restored before replaying
+    pub start: SceneSnapshot,
+    pub recorded_state: RecordedState,
+
+    pub events: Vec<Recorded>,
+
+    /// State when the recording stopped, compared against after replaying
+    pub end: SceneSnapshot,
+}
+
+impl Recording {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut recording: Self = ron::de::from_str(&fs::read_to_string(path)?)?;
+
+        if recording.version != VERSION {
+            return Err(eyre!(
+                "Recording has version {}, expected {}",
+                recording.version,
+                VERSION
+            ));
+        }
+
+        recording.recorded_state.camera_path.rebuild();
+        Ok(recording)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let ron = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, ron)?;
+
+        Ok(())
+    }
+}
+
+/// Collects input events and frames while recording.
+pub struct Recorder {
+    started: Instant,
+    recording: Recording,
+}
+
+impl Recorder {
+    pub fn new(start: SceneSnapshot, recorded_state: RecordedState) -> Self {
+        Self {
+            started: Instant::now(),
+            recording: Recording {
+                version: VERSION,
+                end: start.clone(),
+                start,
+                recorded_state,
+                events: vec![],
+            },
+        }
+    }
+
+    fn time(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    pub fn input(&mut self, target: Target, event: RecordedInput) {
+        let time = self.time();
+        self.recording.events.push(Recorded::Input {
+            time,
+            target,
+            event,
+        });
+    }
+
+    pub fn edit(&mut self, scene: SceneSnapshot) {
+        let time = self.time();
+        self.recording.events.push(Recorded::Edit { time, scene });
+    }
+
+    pub fn frame(&mut self, delta: f32) {
+        let time = self.time();
+        self.recording.events.push(Recorded::Frame { time, delta });
+    }
+
+    pub fn finish(mut self, end: SceneSnapshot) -> Recording {
+        self.recording.end = end;
+        self.recording
+    }
+}
+
+/// Something that happened between two frames, in the order it happened.
+#[derive(Debug)]
+pub enum Replayed {
+    Input(Target, RecordedInput),
+    /// Restore the scene to what an edit left it at
+    Edit(SceneSnapshot),
+}
+
+/// Inputs and edits received between two frames.
+pub type FrameInputs = Vec<Replayed>;
+
+/// Feeds a recording back one frame at a time.
+pub struct Replay {
+    events: VecDeque<Recorded>,
+    pub end: SceneSnapshot,
+}
+
+impl Replay {
+    /// Returns the replay and the state it should start from.
+    pub fn new(recording: Recording) -> (Self, SceneSnapshot, RecordedState) {
+        info!("Replaying {} events", recording.events.len());
+
+        let replay = Self {
+            events: recording.events.into(),
+            end: recording.end,
+        };
+
+        (replay, recording.start, recording.recorded_state)
+    }
+
+    /// The inputs leading up to the next frame, and that frame's delta time.
+    /// Inputs recorded after the last frame come with a `None` delta.
+    /// Returns `None` when the replay is done.
+    pub fn next_frame(&mut self) -> Option<(FrameInputs, Option<f32>)> {
+        if self.events.is_empty() {
+            return None;
+        }
+
+        let mut inputs = vec![];
+        while let Some(recorded) = self.events.pop_front() {
+            match recorded {
+                Recorded::Input { target, event, .. } => {
+                    inputs.push(Replayed::Input(target, event))
+                }
+                Recorded::Edit { scene, .. } => inputs.push(Replayed::Edit(scene)),
+                Recorded::Frame { delta, .. } => return Some((inputs, Some(delta))),
+            }
+        }
+
+        Some((inputs, None))
+    }
+}
@@ -5,15 +5,29 @@ use wgpu::{
     BindGroupLayoutEntry, BindingType, BufferAddress, BufferBindingType, BufferDescriptor,
     BufferSize, BufferUsages, ShaderStages,
 };
+use winit::dpi::PhysicalSize;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, Pod, Zeroable)]
 pub struct ResolutionUniform {
-    /// The resolution of the window,
+    /// The resolution of the window, in physical pixels
     pub resolution: [f32; 2],
+
+    /// Physical pixels per logical pixel
+    pub scale_factor: f32,
+
+    _padding: f32,
 }
 
 impl ResolutionUniform {
+    pub fn new(size: PhysicalSize<u32>, scale_factor: f64) -> Self {
+        Self {
+            resolution: [size.width as f32, size.height as f32],
+            scale_factor: scale_factor as f32,
+            _padding: 0.0,
+        }
+    }
+
     pub const fn size(&self) -> BufferAddress {
         mem::size_of::<ResolutionUniform>() as BufferAddress
     }
@@ -31,7 +45,7 @@ impl ResolutionUniform {
         }
     }
 
-    pub fn buffer_descriptor(&self) -> BufferDescriptor {
+    pub fn buffer_descriptor(&self) -> BufferDescriptor<'_> {
         BufferDescriptor {
             label: Some("Resolution"),
             size: self.size(),
@@ -40,7 +54,7 @@ impl ResolutionUniform {
         }
     }
 
-    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
-        self.resolution = [size.width as f32, size.height as f32];
+    pub fn resize(&mut self, size: PhysicalSize<u32>, scale_factor: f64) {
+        *self = Self::new(size, scale_factor);
     }
 }
@@ -0,0 +1,183 @@
+use std::{
+    collections::BTreeMap,
+    ops::Deref,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use wgpu::{
+    Buffer, BufferDescriptor, Device, QuerySet, QuerySetDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Texture, TextureDescriptor, QUERY_SIZE,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Buffer,
+    Texture,
+    QuerySet,
+    RenderPipeline,
+}
+
+#[derive(Debug)]
+struct Entry {
+    kind: Kind,
+    label: String,
+    bytes: u64,
+}
+
+/// Every tracked resource alive, and how many have been created under each label.
+#[derive(Debug)]
+struct Inventory {
+    next_id: u64,
+    live: BTreeMap<u64, Entry>,
+    created: BTreeMap<(Kind, String), u64>,
+}
+
+static INVENTORY: Mutex<Inventory> = Mutex::new(Inventory {
+    next_id: 0,
+    live: BTreeMap::new(),
+    created: BTreeMap::new(),
+});
+
+fn inventory() -> MutexGuard<'static, Inventory> {
+    // Nothing is left half-done while holding the lock, so a panic elsewhere doesn't matter
+    INVENTORY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A GPU resource, listed in the inventory for as long as it is alive.
+#[derive(Debug)]
+pub struct Tracked<T> {
+    resource: T,
+    id: u64,
+}
+
+impl<T> Tracked<T> {
+    fn new(resource: T, kind: Kind, label: Option<&str>, bytes: u64) -> Self {
+        let label = label.unwrap_or("(unlabelled)").to_string();
+
+        let mut inventory = inventory();
+        let id = inventory.next_id;
+        inventory.next_id += 1;
+
+        *inventory.created.entry((kind, label.clone())).or_default() += 1;
+        inventory.live.insert(id, Entry { kind, label, bytes });
+
+        Self { resource, id }
+    }
+}
+
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.resource
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        inventory().live.remove(&self.id);
+    }
+}
+
+pub fn create_buffer(device: &Device, descriptor: &BufferDescriptor) -> Tracked<Buffer> {
+    Tracked::new(
+        device.create_buffer(descriptor),
+        Kind::Buffer,
+        descriptor.label,
+        descriptor.size,
+    )
+}
+
+/// The size counts every mip level and sample.
+pub fn create_texture(device: &Device, descriptor: &TextureDescriptor) -> Tracked<Texture> {
+    let info = descriptor.format.describe();
+    let (block_width, block_height) = (
+        info.block_dimensions.0 as u64,
+        info.block_dimensions.1 as u64,
+    );
+
+    let size = descriptor.size;
+    let bytes = (0..descriptor.mip_level_count)
+        .map(|level| {
+            let width = (size.width as u64 >> level).max(1);
+            let height = (size.height as u64 >> level).max(1);
+
+            width.div_ceil(block_width)
+                * height.div_ceil(block_height)
+                * size.depth_or_array_layers as u64
+                * info.block_size as u64
+        })
+        .sum::<u64>()
+        * descriptor.sample_count as u64;
+
+    Tracked::new(
+        device.create_texture(descriptor),
+        Kind::Texture,
+        descriptor.label,
+        bytes,
+    )
+}
+
+pub fn create_query_set(device: &Device, descriptor: &QuerySetDescriptor) -> Tracked<QuerySet> {
+    Tracked::new(
+        device.create_query_set(descriptor),
+        Kind::QuerySet,
+        descriptor.label,
+        (descriptor.count * QUERY_SIZE) as u64,
+    )
+}
+
+/// Pipelines are listed without a size, the driver decides that.
+pub fn create_render_pipeline(
+    device: &Device,
+    descriptor: &RenderPipelineDescriptor,
+) -> Tracked<RenderPipeline> {
+    Tracked::new(
+        device.create_render_pipeline(descriptor),
+        Kind::RenderPipeline,
+        descriptor.label,
+        0,
+    )
+}
+
+/// The live resources per kind and label, and how many were ever created.
+/// Far more created than alive means something is recreated over and over.
+pub fn inventory_lines() -> Vec<String> {
+    let inventory = inventory();
+
+    let mut live = BTreeMap::<(Kind, &str), (u64, u64)>::new();
+    for entry in inventory.live.values() {
+        let (count, bytes) = live.entry((entry.kind, &entry.label)).or_default();
+        *count += 1;
+        *bytes += entry.bytes;
+    }
+
+    let mut lines = inventory
+        .created
+        .iter()
+        .map(|((kind, label), created)| {
+            let (count, bytes) = live
+                .get(&(*kind, label.as_str()))
+                .copied()
+                .unwrap_or_default();
+
+            format!(
+                "{:?} {:?}: {} alive, {:.1} KiB, {} created",
+                kind,
+                label,
+                count,
+                bytes as f64 / 1024.0,
+                created
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let total = live.values().map(|(_, bytes)| bytes).sum::<u64>();
+    lines.push(format!(
+        "Total: {} alive, {:.1} KiB",
+        inventory.live.len(),
+        total as f64 / 1024.0
+    ));
+
+    lines
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bindings::{Action, Bindings, Scope},
+    camera::Camera,
+    camera_path::{CameraPath, Keyframe},
+    input::InputState,
+    radar::RadarUniform,
+    square::Square,
+};
+
+/// How far vertices move per key press, to begin with.
+const DISPLACE_AMOUNT: f32 = 0.05;
+
+/// The part of the scene that recordings and sessions save.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneSnapshot {
+    pub camera: Camera,
+    pub square: Square,
+    pub displace_amount: f32,
+}
+
+impl Default for SceneSnapshot {
+    fn default() -> Self {
+        Self {
+            camera: Camera::default(),
+            square: Square::default(),
+            displace_amount: DISPLACE_AMOUNT,
+        }
+    }
+}
+
+/// State shared by all windows, outliving any one of them.
+#[derive(Debug, Default)]
+pub struct Scene {
+    pub camera: Camera,
+    pub square: Square,
+    pub displace_amount: f32,
+
+    /// Radar settings. Direction and position follow the camera when drawn.
+    pub radar: RadarUniform,
+
+    pub camera_path: CameraPath,
+
+    /// The camera path keyframe last clicked
+    pub selected_keyframe: Option<usize>,
+
+    /// Whether the main window shows vertex handles that can be dragged
+    pub editing: bool,
+
+    /// Whether dragged vertices snap to multiples of the vertex step size
+    pub snap: bool,
+}
+
+impl Scene {
+    pub fn new(snapshot: SceneSnapshot, radar: RadarUniform) -> Self {
+        let mut scene = Self {
+            radar,
+            ..Default::default()
+        };
+        scene.restore(snapshot);
+
+        scene
+    }
+
+    pub fn snapshot(&self) -> SceneSnapshot {
+        SceneSnapshot {
+            camera: self.camera.clone(),
+            square: self.square.clone(),
+            displace_amount: self.displace_amount,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: SceneSnapshot) {
+        self.camera = snapshot.camera;
+        self.square = snapshot.square;
+        self.displace_amount = snapshot.displace_amount;
+    }
+
+    /// Put the camera at a camera path keyframe.
+    pub fn fly_to(&mut self, keyframe: &Keyframe) {
+        keyframe.apply(&mut self.camera);
+    }
+
+    /// Move the camera according to held keys, if the main window has focus.
+    /// `focused` is the scope of the window that has it.
+    /// Returns true while the camera is still in motion.
+    pub fn update(
+        &mut self,
+        input: &InputState,
+        bindings: &Bindings,
+        focused: Option<Scope>,
+        dt: f32,
+    ) -> bool {
+        let (thrust, turn) = if focused == Some(Scope::Main) {
+            (
+                (
+                    bindings.axis(input, Scope::Main, Action::MoveLeft, Action::MoveRight),
+                    bindings.axis(input, Scope::Main, Action::MoveDown, Action::MoveUp),
+                ),
+                bindings.axis(input, Scope::Main, Action::TurnRight, Action::TurnLeft),
+            )
+        } else {
+            // Coasts to a stop
+            ((0.0, 0.0), 0.0)
+        };
+
+        self.camera.update(thrust, turn, dt);
+
+        self.camera.is_moving()
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+use winit::event_loop::ControlFlow;
+
+use crate::globals::GlobalsUniform;
+
+/// How long we sleep at most between event loop wakeups.
+/// Keeps things like the shader watcher responsive when nothing is drawn.
+const MAX_WAIT: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameMode {
+    /// Only draw when something asked for a new frame.
+    OnDemand,
+
+    /// Draw as fast as presenting allows, i.e. paced by vsync.
+    Continuous,
+
+    /// Draw at most this many frames per second.
+    Capped(u32),
+}
+
+impl FrameMode {
+    /// The mode after this one, capping at `fps` frames per second.
+    fn next(self, fps: u32) -> Self {
+        match self {
+            FrameMode::OnDemand => FrameMode::Continuous,
+            FrameMode::Continuous => FrameMode::Capped(fps),
+            FrameMode::Capped(_) => FrameMode::OnDemand,
+        }
+    }
+}
+
+pub struct Scheduler {
+    mode: FrameMode,
+
+    /// Frames per second when cycling to [`FrameMode::Capped`]
+    fps_cap: u32,
+
+    start: Instant,
+    last_frame: Instant,
+    next_frame: Instant,
+    frame_requested: bool,
+    frames: u32,
+    pub globals: GlobalsUniform,
+}
+
+impl Scheduler {
+    pub fn new(mode: FrameMode, fps_cap: u32) -> Self {
+        let now = Instant::now();
+
+        Self {
+            mode,
+            fps_cap,
+            start: now,
+            last_frame: now,
+            next_frame: now,
+            // Always draw the first frame
+            frame_requested: true,
+            frames: 0,
+            globals: GlobalsUniform::default(),
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: FrameMode) {
+        info!("Frame mode: {:?}", mode);
+
+        self.mode = mode;
+        self.next_frame = Instant::now();
+        self.frame_requested = true;
+    }
+
+    pub fn cycle_mode(&mut self) {
+        self.set_mode(self.mode.next(self.fps_cap));
+    }
+
+    /// Ask for a frame to be drawn the next time the scheduler is polled.
+    /// Only matters in [`FrameMode::OnDemand`], the other modes draw regardless.
+    pub fn request_frame(&mut self) {
+        self.frame_requested = true;
+    }
+
+    /// Whether a frame should be drawn now.
+    ///
+    /// Should be called once per event loop iteration, i.e. on `MainEventsCleared`.
+    pub fn frame_due(&mut self, now: Instant) -> bool {
+        match self.mode {
+            FrameMode::OnDemand => std::mem::take(&mut self.frame_requested),
+            FrameMode::Continuous => true,
+            FrameMode::Capped(fps) => {
+                let period = Duration::from_secs_f64(1.0 / fps.max(1) as f64);
+
+                let due = now >= self.next_frame;
+                if due {
+                    self.next_frame += period;
+
+                    // Don't try to catch up if we fell far behind
+                    if self.next_frame < now {
+                        self.next_frame = now + period;
+                    }
+                }
+
+                due
+            }
+        }
+    }
+
+    /// How long the event loop may sleep before the next frame is due.
+    pub fn control_flow(&self, now: Instant) -> ControlFlow {
+        match self.mode {
+            FrameMode::OnDemand if self.frame_requested => ControlFlow::Poll,
+            FrameMode::OnDemand => ControlFlow::WaitUntil(now + MAX_WAIT),
+            FrameMode::Continuous => ControlFlow::Poll,
+            FrameMode::Capped(_) => ControlFlow::WaitUntil(self.next_frame.min(now + MAX_WAIT)),
+        }
+    }
+
+    /// Advance to the next frame, updating the globals.
+    ///
+    /// The delta is capped, so coming back from a long idle period in
+    /// [`FrameMode::OnDemand`] does not look like one huge frame.
+    pub fn tick(&mut self, now: Instant) -> GlobalsUniform {
+        let delta = now.saturating_duration_since(self.last_frame).min(MAX_WAIT);
+        self.last_frame = now;
+
+        self.globals = GlobalsUniform {
+            time: now.saturating_duration_since(self.start).as_secs_f32(),
+            delta: delta.as_secs_f32(),
+            frame: self.frames,
+            ..self.globals
+        };
+        self.frames = self.frames.wrapping_add(1);
+
+        self.globals
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{app_window::AppWindow, gpu::DeviceContext, offscreen::Offscreen, scene::Scene};
+use color_eyre::{eyre::eyre, Result};
+
+/// A fresh path in `dir` for a screenshot of the named window.
+/// Creates `dir` if needed.
+fn path(dir: &Path, window: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let timestamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S%.3f");
+    Ok(dir.join(format!("{}_{}.png", window, timestamp)))
+}
+
+/// Render the window again at its surface size, offscreen,
+/// and save the result in `dir`. Returns where it was saved.
+pub fn capture(
+    window: &mut dyn AppWindow,
+    scene: &Scene,
+    dir: &Path,
+    context: DeviceContext,
+) -> Result<PathBuf> {
+    let DeviceContext { device, queue, .. } = context;
+    let size = window.size();
+    if size.width == 0 || size.height == 0 {
+        return Err(eyre!("{:?} window is minimised", window.target()));
+    }
+
+    let offscreen = Offscreen::new(device, size, window.texture_format());
+    window.render_to(context, scene, &offscreen.view);
+
+    let name = format!("{:?}", window.target()).to_lowercase();
+    let path = path(dir, &name)?;
+    offscreen.save_png(device, queue, &path)?;
+
+    Ok(path)
+}
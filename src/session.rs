@@ -0,0 +1,207 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::{eyre::eyre, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition},
+    window::Window,
+};
+
+use crate::{app::App, radar::RadarUniform, recording::Target, scene::SceneSnapshot};
+
+/// Bumped whenever the file layout changes in an incompatible way.
+const VERSION: u32 = 5;
+
+/// The files the windows are drawn from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Maps {
+    /// Shader drawing the main window
+    pub shader: PathBuf,
+
+    /// Colour map, shown on the left of the extra window
+    pub diffuse_map: PathBuf,
+
+    /// Height map, shown on the right of the extra window
+    pub height_map: PathBuf,
+}
+
+impl Maps {
+    /// Check the images can be read, before windows are rebuilt from them.
+    pub fn check(&self) -> Result<()> {
+        for path in [&self.diffuse_map, &self.height_map] {
+            image::open(path).map_err(|e| eyre!("{}: {}", path.display(), e))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Maps {
+    fn default() -> Self {
+        Self {
+            shader: concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/radar.wgsl").into(),
+            diffuse_map: concat!(env!("CARGO_MANIFEST_DIR"), "/src/aztec-diffuse.png").into(),
+            height_map: concat!(env!("CARGO_MANIFEST_DIR"), "/src/aztec-height.png").into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    /// Inner size, so windows keep their apparent size on monitors with another scale factor
+    pub size: LogicalSize<u32>,
+
+    /// Top left corner of the window decorations, in physical pixels as monitors are laid out
+    pub position: PhysicalPosition<i32>,
+}
+
+impl WindowGeometry {
+    fn of(window: &Window) -> Option<Self> {
+        Some(Self {
+            size: window.inner_size().to_logical(window.scale_factor()),
+            position: window.outer_position().ok()?,
+        })
+    }
+
+    pub fn apply(&self, window: &Window) {
+        window.set_inner_size(self.size);
+        window.set_outer_position(self.position);
+    }
+}
+
+/// Everything restored on startup and saved on exit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+
+    pub main_window: WindowGeometry,
+    pub extra_window: WindowGeometry,
+    pub profile_window: WindowGeometry,
+
+    pub maps: Maps,
+
+    pub main: SceneSnapshot,
+    pub radar: RadarUniform,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            main_window: WindowGeometry {
+                size: LogicalSize::new(600, 600),
+                position: PhysicalPosition::new(0, 300),
+            },
+            extra_window: WindowGeometry {
+                size: LogicalSize::new(800, 600),
+                position: PhysicalPosition::new(620, 300),
+            },
+            profile_window: WindowGeometry {
+                size: LogicalSize::new(600, 300),
+                position: PhysicalPosition::new(1440, 300),
+            },
+            maps: Maps::default(),
+            main: SceneSnapshot::default(),
+            radar: RadarUniform::default(),
+        }
+    }
+}
+
+impl Session {
+    fn try_load(path: &Path) -> Result<Self> {
+        let session: Self = ron::de::from_str(&fs::read_to_string(path)?)?;
+
+        if session.version != VERSION {
+            return Err(eyre!(
+                "Session has version {}, expected {}",
+                session.version,
+                VERSION
+            ));
+        }
+
+        Ok(session)
+    }
+
+    /// The session saved at `path`, or the defaults if there is none.
+    /// A file that can't be used is moved aside to `<path>.bak` rather than
+    /// being overwritten on exit.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Self::default();
+        }
+
+        match Self::try_load(path) {
+            Ok(session) => {
+                info!("Restored session from {:?}", path);
+                session
+            }
+            Err(e) => {
+                let backup = with_suffix(path, ".bak");
+                warn!(
+                    "Could not restore session from {:?}, moving it to {:?} and using defaults: {:#}",
+                    path, backup, e
+                );
+
+                if let Err(e) = fs::rename(path, &backup) {
+                    warn!("Could not move broken session aside: {}", e);
+                }
+
+                Self::default()
+            }
+        }
+    }
+
+    /// Write to a temporary file first, so a crash mid-save can't corrupt the session.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let ron = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+
+        let temporary = with_suffix(path, ".tmp");
+        fs::write(&temporary, ron)?;
+        fs::rename(&temporary, path).or_else(|e| match e.kind() {
+            // Renaming over an existing file fails on some platforms
+            io::ErrorKind::AlreadyExists => {
+                fs::remove_file(path)?;
+                fs::rename(&temporary, path)
+            }
+            _ => Err(e),
+        })?;
+
+        Ok(())
+    }
+
+    /// Take in the current state of the scene and the open windows.
+    /// Geometry is kept as is for closed windows and any window which can't
+    /// tell where it is.
+    pub fn update(&mut self, app: &App) {
+        for window in app.windows.values() {
+            let geometry = window
+                .viewport()
+                .and_then(|viewport| WindowGeometry::of(&viewport.window));
+
+            match (window.target(), geometry) {
+                (Target::Main, Some(geometry)) => self.main_window = geometry,
+                (Target::Extra, Some(geometry)) => self.extra_window = geometry,
+                (Target::Profile, Some(geometry)) => self.profile_window = geometry,
+                _ => {}
+            }
+        }
+
+        self.main = app.scene.snapshot();
+        self.radar = app.scene.radar;
+    }
+}
+
+/// `path` with `suffix` after its whole file name, whatever its extension.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+
+    PathBuf::from(name)
+}
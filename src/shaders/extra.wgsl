@@ -27,6 +27,16 @@ var t_diffuse_left: texture_2d<f32>;
 [[group(0), binding(1)]]
 var s_diffuse: sampler;
 
+[[block]]
+struct Globals {
+    time: f32;
+    delta: f32;
+    frame: u32;
+};
+
+[[group(0), binding(2)]]
+var<uniform> globals: Globals;
+
 [[stage(fragment)]]
 fn fs_main(
 	in: VertexStageOutput
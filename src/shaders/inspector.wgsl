@@ -0,0 +1,59 @@
+struct VertexStageOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] colour: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+	[[location(0)]] in_position: vec2<f32>,
+	[[location(1)]] in_tex_coords: vec2<f32>,
+	[[location(2)]] in_colour: vec4<f32>,
+) -> VertexStageOutput {
+	var out: VertexStageOutput;
+
+	out.position = vec4<f32>(
+		in_position,
+		0.0,
+		1.0
+	);
+	out.tex_coords = in_tex_coords;
+	out.colour = in_colour;
+
+	return out;
+}
+
+[[group(0), binding(0)]]
+var t_font: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var s_font: sampler;
+
+fn linear_from_srgb(srgb: vec3<f32>) -> vec3<f32> {
+	let lower = srgb / vec3<f32>(12.92);
+	let higher = pow((srgb + vec3<f32>(0.055)) / vec3<f32>(1.055), vec3<f32>(2.4));
+
+	return select(higher, lower, srgb <= vec3<f32>(0.04045));
+}
+
+// egui's colours are sRGB with premultiplied alpha, and its font texture is white,
+// so shapes and glyphs are cut out by the texture's alpha alone.
+// For windows drawing into linear formats, which show colours as they are written
+[[stage(fragment)]]
+fn fs_main(
+	in: VertexStageOutput
+) -> [[location(0)]] vec4<f32> {
+	let coverage = textureSample(t_font, s_font, in.tex_coords).a;
+
+	return in.colour * coverage;
+}
+
+// For windows drawing into sRGB formats, which expect linear colours
+[[stage(fragment)]]
+fn fs_main_srgb(
+	in: VertexStageOutput
+) -> [[location(0)]] vec4<f32> {
+	let coverage = textureSample(t_font, s_font, in.tex_coords).a;
+
+	return vec4<f32>(linear_from_srgb(in.colour.rgb), in.colour.a) * coverage;
+}
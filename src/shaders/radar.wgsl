@@ -1,5 +1,8 @@
 let PI: f32 = 3.14159265359;
 
+// Radius of the radar disc, in logical pixels
+let RADIUS: f32 = 150.0;
+
 struct VertexStageOutput {
     [[builtin(position)]] position: vec4<f32>;
     [[location(0)]] tex_coords: vec2<f32>;
@@ -8,13 +11,22 @@ struct VertexStageOutput {
 [[block]]
 struct Radar {
     view_dir: vec2<f32>;
-    position: vec2<f32>;
+    position: vec2<f32>; // Physical pixels from the top left, like fragment positions
     fov: vec2<f32>; // Only first value is used (radians), second value for alignment
+    pitch: f32; // Radians, up is positive
 };
 
 [[block]]
 struct Resolution {
-    size: vec2<f32>;
+    size: vec2<f32>; // Physical pixels
+    scale_factor: f32; // Physical pixels per logical pixel
+};
+
+[[block]]
+struct Globals {
+    time: f32;
+    delta: f32;
+    frame: u32;
 };
 
 [[group(0), binding(0)]]
@@ -23,6 +35,9 @@ var<uniform> radar: Radar;
 [[group(0), binding(1)]]
 var<uniform> resolution: Resolution;
 
+[[group(0), binding(2)]]
+var<uniform> globals: Globals;
+
 [[stage(vertex)]]
 fn vs_main(
 	[[location(0)]] in_position: vec2<f32>,
@@ -43,15 +58,15 @@ fn vs_main(
 fn fs_main(
 	in: VertexStageOutput
 ) -> [[location(0)]] vec4<f32> { 
-	let position = vec2<f32>(in.position.xy - radar.position);
-    let uv = vec2<f32>(position / resolution.size);
-	let unit = (uv * 2.0) - vec2<f32>(1.0, 1.0);
-	let at_radar = vec2<f32>(in.position.xy);
+	// From the radar to the fragment in logical pixels, so the disc is as large on any monitor
+	let offset = (in.position.xy - radar.position) / resolution.scale_factor;
+	// Fragment positions grow downwards, the view direction upwards
+	let unit = vec2<f32>(offset.x, -offset.y) / RADIUS;
 
 	let angle_from_view_direction = acos(dot(radar.view_dir, unit) / (length(radar.view_dir) * length(unit)));
 	let fov = radar.fov.x;
 
-	if (abs(angle_from_view_direction) < (fov / 2.0) && distance(unit, vec2<f32>(0.0, 0.0)) < 0.5) {
+	if (abs(angle_from_view_direction) < (fov / 2.0) && length(unit) < cos(radar.pitch)) {
 		return vec4<f32>(1.0, 0.2, 0.3, 0.3);
 	} else {
 		return vec4<f32>(0.2, 1.0, 0.0, 1.0);
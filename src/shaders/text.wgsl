@@ -0,0 +1,40 @@
+struct VertexStageOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] colour: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+	[[location(0)]] in_position: vec2<f32>,
+	[[location(1)]] in_tex_coords: vec2<f32>,
+	[[location(2)]] in_colour: vec4<f32>,
+) -> VertexStageOutput {
+	var out: VertexStageOutput;
+
+	out.position = vec4<f32>(
+		in_position,
+		0.0,
+		1.0
+	);
+	out.tex_coords = in_tex_coords;
+	out.colour = in_colour;
+
+	return out;
+}
+
+[[group(0), binding(0)]]
+var t_atlas: texture_2d<f32>;
+
+[[group(0), binding(1)]]
+var s_atlas: sampler;
+
+// The atlas is white, glyphs are cut out by its alpha
+[[stage(fragment)]]
+fn fs_main(
+	in: VertexStageOutput
+) -> [[location(0)]] vec4<f32> {
+	let coverage = textureSample(t_atlas, s_atlas, in.tex_coords).a;
+
+	return vec4<f32>(in.colour.rgb, in.colour.a * coverage);
+}
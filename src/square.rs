@@ -1,98 +1,106 @@
-use std::ops::{Index, IndexMut};
+use std::{fs, path::Path};
 
-use wgpu::{
-    util::{BufferInitDescriptor, DeviceExt},
-    Buffer, BufferUsages, Device,
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    mesh::Mesh,
     misc::{self, Direction},
-    vertex::{Vertex, VertexSelected},
 };
 
-#[derive(Debug)]
+/// Half the width and height of the default square, in NDC.
+pub const EXTENT: f32 = 0.8;
+
+/// Bumped whenever the layout file changes in an incompatible way.
+const VERSION: u32 = 1;
+
+/// The mesh shown in the main window, and the vertex the keys move.
+///
+/// A square by default, but any mesh can take its place.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Square {
-    pub vertices: [Vertex; 4],
-    pub indices: [u16; 6],
-    pub selected: VertexSelected,
+    pub mesh: Mesh,
+    pub selected: usize,
+}
+
+/// A square's mesh as saved on its own, so warped surfaces can be kept and loaded again.
+#[derive(Debug, Serialize, Deserialize)]
+struct Layout {
+    version: u32,
+    mesh: Mesh,
 }
 
 impl Square {
-    /// Given vertices in clockwise order, sets up the rest.
-    pub fn new_from_vertices(vertices: [Vertex; 4]) -> Self {
-        Square::new(vertices, [0, 1, 3, 3, 1, 2], VertexSelected::One)
+    pub fn new(mesh: Mesh) -> Self {
+        Self { mesh, selected: 0 }
     }
 
-    pub fn new(vertices: [Vertex; 4], indices: [u16; 6], selected: VertexSelected) -> Self {
-        Self {
-            vertices,
-            indices,
-            selected,
+    /// A square from a layout saved with [`Square::save`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let ron = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Reading layout {}", path.display()))?;
+        let layout: Layout = ron::de::from_str(&ron)
+            .wrap_err_with(|| format!("Parsing layout {}", path.display()))?;
+
+        if layout.version != VERSION {
+            return Err(eyre!(
+                "Layout {} has version {}, expected {}",
+                path.display(),
+                layout.version,
+                VERSION
+            ));
         }
-    }
 
-    pub fn displace(&mut self, direction: Direction, amount: f32) {
-        let (x, y) = misc::displace(direction, amount);
+        Ok(Self::new(layout.mesh))
+    }
 
-        let selected = self.selected;
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let layout = Layout {
+            version: VERSION,
+            mesh: self.mesh.clone(),
+        };
+        let ron = ron::ser::to_string_pretty(&layout, ron::ser::PrettyConfig::default())?;
+        let path = path.as_ref();
+        fs::write(path, ron).wrap_err_with(|| format!("Writing layout {}", path.display()))?;
 
-        self[selected].pos[0] += x;
-        self[selected].pos[1] += y;
+        Ok(())
     }
 
-    pub fn vertex_buffer(&self, device: &Device) -> Buffer {
-        device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Vertex buffer :)"),
-            contents: bytemuck::cast_slice(&self.vertices),
-            usage: BufferUsages::VERTEX,
-        })
-    }
+    pub fn displace(&mut self, direction: Direction, amount: f32) {
+        let (x, y) = misc::displace(direction, amount);
 
-    pub fn index_buffer(&self, device: &Device) -> Buffer {
-        device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Index buffer :)"),
-            contents: bytemuck::cast_slice(&self.indices),
-            usage: BufferUsages::INDEX,
-        })
+        self.mesh.displace(self.selected, x, y);
     }
 
-    /// Set the square's selected.
-    pub fn set_selected(&mut self, selected: VertexSelected) {
-        self.selected = selected;
-    }
-}
+    /// Put a vertex at (x, y), kept within the window.
+    /// With a `grid` step, it lands on the nearest multiple of it instead.
+    pub fn move_vertex(&mut self, index: usize, x: f32, y: f32, grid: Option<f32>) {
+        let snap = |value: f32| {
+            let value = match grid {
+                Some(grid) if grid > 0.0 => (value / grid).round() * grid,
+                _ => value,
+            };
 
-impl Default for Square {
-    fn default() -> Self {
-        Square::new_from_vertices([
-            Vertex::new(-0.8, 0.8, 0.0, 0.0),
-            Vertex::new(0.8, 0.8, 1.0, 0.0),
-            Vertex::new(0.8, -0.8, 1.0, 1.0),
-            Vertex::new(-0.8, -0.8, 0.0, 1.0),
-        ])
-    }
-}
+            value.clamp(-1.0, 1.0)
+        };
 
-impl Index<VertexSelected> for Square {
-    type Output = Vertex;
+        self.mesh.move_vertex(index, snap(x), snap(y));
+    }
 
-    fn index(&self, vertex: VertexSelected) -> &Self::Output {
-        match vertex {
-            VertexSelected::One => &self.vertices[0],
-            VertexSelected::Two => &self.vertices[1],
-            VertexSelected::Three => &self.vertices[2],
-            VertexSelected::Four => &self.vertices[3],
+    /// Set the square's selected vertex, if the mesh has one at `selected`.
+    pub fn set_selected(&mut self, selected: usize) {
+        if selected < self.mesh.vertices().len() {
+            self.selected = selected;
         }
     }
 }
 
-impl IndexMut<VertexSelected> for Square {
-    fn index_mut(&mut self, vertex: VertexSelected) -> &mut Self::Output {
-        match vertex {
-            VertexSelected::One => &mut self.vertices[0],
-            VertexSelected::Two => &mut self.vertices[1],
-            VertexSelected::Three => &mut self.vertices[2],
-            VertexSelected::Four => &mut self.vertices[3],
-        }
+impl Default for Square {
+    fn default() -> Self {
+        Square::new(Mesh::quad([-EXTENT, EXTENT], [EXTENT, -EXTENT]))
     }
 }
@@ -0,0 +1,397 @@
+use bytemuck::{Pod, Zeroable};
+use color_eyre::Result;
+use wgpu::*;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    gpu_mesh::GpuMesh,
+    pipeline::{BindLayout, PipelineBuilder, PipelineCache, Shader, SharedPipeline},
+    texture_image::{self, TextureImage},
+    vertex_layout::VertexLayout,
+};
+
+/// The X11 "misc-fixed" 8x13 font, which is in the public domain.
+/// Printable ASCII and DEL, 16 glyphs per row, one bit per pixel with the leftmost pixel in the highest bit.
+const FONT: &[u8] = include_bytes!("font_8x13.raw");
+
+const GLYPH_WIDTH: u32 = 8;
+const GLYPH_HEIGHT: u32 = 13;
+const GLYPHS_PER_ROW: u32 = 16;
+const GLYPH_COUNT: u32 = 96;
+
+const ATLAS_WIDTH: u32 = GLYPH_WIDTH * GLYPHS_PER_ROW;
+
+/// The glyphs, then a row of opaque pixels to draw background boxes with.
+const ATLAS_HEIGHT: u32 = GLYPH_HEIGHT * GLYPH_COUNT / GLYPHS_PER_ROW + 1;
+
+/// Drawn instead of characters the font doesn't have.
+const REPLACEMENT: char = '?';
+
+/// Space between text and the edge of its background box, in lines.
+const PADDING: f32 = 0.25;
+
+/// Distance of the help text from the window's top left corner, in logical pixels.
+const HELP_MARGIN: f32 = 12.0;
+
+const HELP_STYLE: TextStyle = TextStyle {
+    size: GLYPH_HEIGHT as f32,
+    colour: [1.0, 1.0, 1.0, 1.0],
+    background: Some([0.0, 0.0, 0.0, 0.75]),
+    align: Align::Left,
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable, VertexLayout, Default, PartialEq)]
+pub struct TextVertex {
+    pub pos: [f32; 2],
+    pub tc: [f32; 2],
+    pub colour: [f32; 4],
+}
+
+/// Where lines are placed relative to the position text is drawn at.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Align {
+    /// Lines start at the position
+    #[default]
+    Left,
+    /// Lines are centred on the position
+    Centre,
+    /// Lines end at the position
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    /// Line height, in logical pixels.
+    /// Multiples of 13 keep glyphs crisp.
+    pub size: f32,
+    pub colour: [f32; 4],
+
+    /// Colour of a box drawn behind the text, if any
+    pub background: Option<[f32; 4]>,
+    pub align: Align,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            size: GLYPH_HEIGHT as f32,
+            colour: [1.0, 1.0, 1.0, 1.0],
+            background: None,
+            align: Align::Left,
+        }
+    }
+}
+
+/// Atlas texture coordinates of a character, as left, top, right, bottom.
+fn glyph_tc(character: char) -> [f32; 4] {
+    let index = match character {
+        ' '..='~' => character as u32 - ' ' as u32,
+        _ => REPLACEMENT as u32 - ' ' as u32,
+    };
+    let (column, row) = (index % GLYPHS_PER_ROW, index / GLYPHS_PER_ROW);
+
+    [
+        (column * GLYPH_WIDTH) as f32 / ATLAS_WIDTH as f32,
+        (row * GLYPH_HEIGHT) as f32 / ATLAS_HEIGHT as f32,
+        ((column + 1) * GLYPH_WIDTH) as f32 / ATLAS_WIDTH as f32,
+        ((row + 1) * GLYPH_HEIGHT) as f32 / ATLAS_HEIGHT as f32,
+    ]
+}
+
+/// Atlas texture coordinates of the opaque row.
+fn solid_tc() -> [f32; 4] {
+    let v = (ATLAS_HEIGHT as f32 - 0.5) / ATLAS_HEIGHT as f32;
+
+    [0.0, v, 1.0, v]
+}
+
+/// White pixels, with the font in the alpha channel.
+fn atlas_pixels() -> Vec<u8> {
+    let bytes_per_row = (ATLAS_WIDTH / 8) as usize;
+
+    (0..ATLAS_HEIGHT as usize)
+        .flat_map(|y| (0..ATLAS_WIDTH as usize).map(move |x| (x, y)))
+        .flat_map(|(x, y)| {
+            let covered = match FONT.get(y * bytes_per_row + x / 8) {
+                Some(byte) => byte & (0x80 >> (x % 8)) != 0,
+                None => true,
+            };
+
+            [255, 255, 255, if covered { 255 } else { 0 }]
+        })
+        .collect()
+}
+
+/// Text to draw into a window in one go.
+///
+/// Positions and sizes are in logical pixels, from the window's top left corner.
+pub struct TextBatch {
+    size: PhysicalSize<u32>,
+    scale_factor: f32,
+
+    vertices: Vec<TextVertex>,
+    indices: Vec<u32>,
+}
+
+impl TextBatch {
+    pub fn new(size: PhysicalSize<u32>, scale_factor: f64) -> Self {
+        Self {
+            size,
+            scale_factor: scale_factor as f32,
+            vertices: vec![],
+            indices: vec![],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn draw_text(&mut self, pos: [f32; 2], size: f32, colour: [f32; 4], text: &str) {
+        self.draw_text_styled(
+            pos,
+            &TextStyle {
+                size,
+                colour,
+                ..Default::default()
+            },
+            text,
+        );
+    }
+
+    /// Each line of `text` is aligned on its own, `pos` is the top of the first line.
+    pub fn draw_text_styled(&mut self, pos: [f32; 2], style: &TextStyle, text: &str) {
+        let advance = style.size * GLYPH_WIDTH as f32 / GLYPH_HEIGHT as f32;
+
+        let lines = text
+            .lines()
+            .map(|line| (line, line.chars().count() as f32 * advance))
+            .collect::<Vec<_>>();
+        let left_of = |width: f32| match style.align {
+            Align::Left => pos[0],
+            Align::Centre => pos[0] - width / 2.0,
+            Align::Right => pos[0] - width,
+        };
+
+        if let Some(background) = style.background {
+            let widest = lines.iter().map(|(_, width)| *width).fold(0.0, f32::max);
+            let padding = style.size * PADDING;
+            let left = left_of(widest);
+
+            self.quad(
+                [
+                    left - padding,
+                    pos[1] - padding,
+                    left + widest + padding,
+                    pos[1] + lines.len() as f32 * style.size + padding,
+                ],
+                solid_tc(),
+                background,
+            );
+        }
+
+        for (row, (line, width)) in lines.iter().enumerate() {
+            let top = pos[1] + row as f32 * style.size;
+            let left = left_of(*width);
+
+            for (column, character) in line.chars().enumerate() {
+                if character == ' ' {
+                    continue;
+                }
+
+                let left = left + column as f32 * advance;
+                self.quad(
+                    [left, top, left + advance, top + style.size],
+                    glyph_tc(character),
+                    style.colour,
+                );
+            }
+        }
+    }
+
+    /// Key bindings in the top left corner.
+    pub fn draw_help(&mut self, lines: &[String]) {
+        self.draw_text_styled([HELP_MARGIN, HELP_MARGIN], &HELP_STYLE, &lines.join("\n"));
+    }
+
+    /// A rectangle given as left, top, right, bottom.
+    fn quad(&mut self, rect: [f32; 4], tc: [f32; 4], colour: [f32; 4]) {
+        let [left, top, right, bottom] = rect;
+        let [u0, v0, u1, v1] = tc;
+
+        let to_ndc = |x: f32, y: f32| {
+            [
+                x * self.scale_factor / self.size.width.max(1) as f32 * 2.0 - 1.0,
+                1.0 - y * self.scale_factor / self.size.height.max(1) as f32 * 2.0,
+            ]
+        };
+
+        let first = self.vertices.len() as u32;
+        let corners = [
+            (to_ndc(left, top), [u0, v0]),
+            (to_ndc(right, top), [u1, v0]),
+            (to_ndc(right, bottom), [u1, v1]),
+            (to_ndc(left, bottom), [u0, v1]),
+        ];
+
+        self.vertices
+            .extend(corners.iter().map(|(pos, tc)| TextVertex {
+                pos: *pos,
+                tc: *tc,
+                colour,
+            }));
+        self.indices
+            .extend([0, 1, 3, 3, 1, 2].iter().map(|i| first + i));
+    }
+}
+
+pub fn bind_group_layout(device: &Device) -> BindLayout {
+    BindLayout::new(
+        device,
+        "Text bind group layout",
+        &[
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler {
+                    filtering: true,
+                    comparison: false,
+                },
+                count: None,
+            },
+        ],
+    )
+}
+
+fn render_pipeline(
+    device: &Device,
+    pipelines: &PipelineCache,
+    layout: &BindLayout,
+    format: &TextureFormat,
+) -> Result<SharedPipeline> {
+    PipelineBuilder::new(
+        "Text render pipeline",
+        Shader::Static {
+            label: "Text shader",
+            source: include_str!("shaders/text.wgsl"),
+        },
+        *format,
+    )
+    .layout("Text pipeline layout", &[layout])
+    .vertex::<TextVertex>()
+    .blend(BlendState::ALPHA_BLENDING)
+    .build(device, pipelines)
+}
+
+/// Draws [`TextBatch`]es over what is already in a window.
+pub struct TextRenderer {
+    render_pipeline: SharedPipeline,
+    bind_group_layout: BindLayout,
+    bind_group: BindGroup,
+    mesh: GpuMesh<TextVertex>,
+
+    #[allow(dead_code)]
+    atlas: TextureImage,
+}
+
+impl TextRenderer {
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        pipelines: &PipelineCache,
+        format: &TextureFormat,
+    ) -> Result<Self> {
+        let atlas = TextureImage::new(
+            "Font atlas",
+            device,
+            ATLAS_WIDTH as usize,
+            ATLAS_HEIGHT as usize,
+            &atlas_pixels(),
+        )?;
+        atlas.write(queue);
+
+        let bind_group_layout = bind_group_layout(device);
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Text bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&atlas.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&texture_image::nearest_sampler(device)),
+                },
+            ],
+        });
+
+        Ok(Self {
+            render_pipeline: render_pipeline(device, pipelines, &bind_group_layout, format)?,
+            bind_group_layout,
+            bind_group,
+            mesh: GpuMesh::new(device, "Text"),
+            atlas,
+        })
+    }
+
+    pub fn set_texture_format(
+        &mut self,
+        device: &Device,
+        pipelines: &PipelineCache,
+        format: &TextureFormat,
+    ) -> Result<()> {
+        self.render_pipeline = render_pipeline(device, pipelines, &self.bind_group_layout, format)?;
+
+        Ok(())
+    }
+
+    pub fn render(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        texture_view: &TextureView,
+        batch: &TextBatch,
+    ) {
+        if batch.is_empty() {
+            return;
+        }
+
+        self.mesh
+            .upload(device, queue, &batch.vertices, &batch.indices);
+
+        encoder.push_debug_group("Text");
+        let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Text render pass"),
+            color_attachments: &[RenderPassColorAttachment {
+                view: texture_view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.insert_debug_marker("Draw glyphs and boxes");
+        self.mesh.draw(&mut rpass);
+
+        drop(rpass);
+        encoder.pop_debug_group();
+    }
+}
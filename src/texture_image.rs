@@ -1,14 +1,30 @@
 use color_eyre::Result;
 use image::GenericImageView;
-use std::{fs::File, io::BufReader, num::NonZeroU32};
+use std::{fs::File, io::BufReader, num::NonZeroU32, path::Path};
 use wgpu::{
-    Device, Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, Texture, TextureAspect,
-    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
-    TextureViewDescriptor,
+    AddressMode, Device, Extent3d, FilterMode, ImageCopyTexture, ImageDataLayout, Origin3d, Queue,
+    Sampler, SamplerDescriptor, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
 };
 
+use crate::resources::{self, Tracked};
+
+/// Samples texels as they are, without blending neighbours or wrapping around the edges.
+pub fn nearest_sampler(device: &Device) -> Sampler {
+    device.create_sampler(&SamplerDescriptor {
+        label: Some("Nearest sampler"),
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Nearest,
+        min_filter: FilterMode::Nearest,
+        mipmap_filter: FilterMode::Nearest,
+        ..Default::default()
+    })
+}
+
 pub struct TextureImage {
-    pub texture: Texture,
+    pub texture: Tracked<Texture>,
     pub texture_view: TextureView,
     pub extent: Extent3d,
     pub data: Vec<u8>,
@@ -30,15 +46,18 @@ impl TextureImage {
             depth_or_array_layers: 1,
         };
 
-        let texture = device.create_texture(&TextureDescriptor {
-            label: Some(label),
-            size: extent,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8UnormSrgb,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-        });
+        let texture = resources::create_texture(
+            device,
+            &TextureDescriptor {
+                label: Some(label),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8UnormSrgb,
+                usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            },
+        );
 
         let texture_view = texture.create_view(&TextureViewDescriptor::default());
 
@@ -50,9 +69,9 @@ impl TextureImage {
         })
     }
 
-    pub fn new_from_path(label: &str, device: &Device, path: &str) -> Result<Self> {
-        let format = image::ImageFormat::from_path(path)?;
-        let reader = BufReader::new(File::open(path)?);
+    pub fn new_from_path<P: AsRef<Path>>(label: &str, device: &Device, path: P) -> Result<Self> {
+        let format = image::ImageFormat::from_path(&path)?;
+        let reader = BufReader::new(File::open(&path)?);
 
         let image = image::load(reader, format)?;
         let data = image.to_rgba8();
@@ -1,21 +1,12 @@
 use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
-pub enum VertexSelected {
-    One,
-    Two,
-    Three,
-    Four,
-}
-
-impl Default for VertexSelected {
-    fn default() -> Self {
-        Self::One
-    }
-}
+use crate::vertex_layout::VertexLayout;
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Pod, Zeroable, Default)]
+#[derive(
+    Debug, Clone, Copy, Pod, Zeroable, VertexLayout, Default, PartialEq, Serialize, Deserialize,
+)]
 pub struct Vertex {
     pub pos: [f32; 2],
     pub tc: [f32; 2],
@@ -0,0 +1,116 @@
+use bytemuck::Pod;
+use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode};
+
+pub use wgpu_explorer_derive::VertexLayout;
+
+/// Vertex types pipelines can read from a vertex buffer.
+///
+/// Derive it with `#[derive(VertexLayout)]` on a `#[repr(C)]` struct
+/// instead of writing out the attributes by hand.
+pub trait VertexLayout: Pod {
+    /// One per field, at shader locations counting up from 0 in field order.
+    const ATTRIBUTES: &'static [VertexAttribute];
+
+    /// The layout of a vertex buffer holding only these vertices.
+    fn layout() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+            attributes: Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Types vertex fields can have, and how the shader reads them.
+pub trait VertexField {
+    const FORMAT: VertexFormat;
+}
+
+macro_rules! vertex_fields {
+    ($($ty:ty => $format:ident),* $(,)?) => {
+        $(
+            impl VertexField for $ty {
+                const FORMAT: VertexFormat = VertexFormat::$format;
+            }
+        )*
+    };
+}
+
+vertex_fields! {
+    f32 => Float32,
+    [f32; 2] => Float32x2,
+    [f32; 3] => Float32x3,
+    [f32; 4] => Float32x4,
+    u32 => Uint32,
+    [u32; 2] => Uint32x2,
+    [u32; 3] => Uint32x3,
+    [u32; 4] => Uint32x4,
+    i32 => Sint32,
+    [i32; 2] => Sint32x2,
+    [i32; 3] => Sint32x3,
+    [i32; 4] => Sint32x4,
+    [u16; 2] => Uint16x2,
+    [u16; 4] => Uint16x4,
+    [u8; 2] => Uint8x2,
+    [u8; 4] => Uint8x4,
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::{Pod, Zeroable};
+    use wgpu::{VertexAttribute, VertexFormat};
+
+    use super::*;
+    use crate::{text::TextVertex, vertex::Vertex};
+
+    fn attribute(format: VertexFormat, offset: BufferAddress, location: u32) -> VertexAttribute {
+        VertexAttribute {
+            format,
+            offset,
+            shader_location: location,
+        }
+    }
+
+    #[test]
+    fn vertex_matches_the_hand_written_layout() {
+        assert_eq!(
+            Vertex::ATTRIBUTES,
+            &[
+                attribute(VertexFormat::Float32x2, 0, 0),
+                attribute(VertexFormat::Float32x2, 8, 1),
+            ]
+        );
+        assert_eq!(Vertex::layout().array_stride, 16);
+    }
+
+    #[test]
+    fn text_vertex_fields_get_consecutive_locations() {
+        let locations = TextVertex::ATTRIBUTES
+            .iter()
+            .map(|attribute| attribute.shader_location)
+            .collect::<Vec<_>>();
+
+        assert_eq!(locations, [0, 1, 2]);
+        assert_eq!(TextVertex::ATTRIBUTES[2].offset, 16);
+    }
+
+    #[test]
+    fn format_attribute_overrides_the_field_type() {
+        #[repr(C)]
+        #[derive(Clone, Copy, Pod, Zeroable, VertexLayout)]
+        struct Coloured {
+            pos: [f32; 3],
+            #[vertex(format = "Unorm8x4")]
+            colour: [u8; 4],
+        }
+
+        assert_eq!(
+            Coloured::ATTRIBUTES,
+            &[
+                attribute(VertexFormat::Float32x3, 0, 0),
+                attribute(VertexFormat::Unorm8x4, 12, 1),
+            ]
+        );
+        assert_eq!(Coloured::layout().array_stride, 16);
+    }
+}
@@ -1,52 +1,213 @@
 use color_eyre::{eyre::ContextCompat, Result};
-use wgpu::{Adapter, Device, Instance, PresentMode, Surface, SurfaceConfiguration, TextureUsages};
+use log::warn;
+use wgpu::{
+    Adapter, Backend, Device, PresentMode, Surface, SurfaceConfiguration, TextureFormat,
+    TextureUsages,
+};
 use winit::{dpi::PhysicalSize, window::Window};
 
+/// The sRGB and linear variants of the formats surfaces commonly prefer.
+const SRGB_PAIRS: [(TextureFormat, TextureFormat); 2] = [
+    (TextureFormat::Bgra8UnormSrgb, TextureFormat::Bgra8Unorm),
+    (TextureFormat::Rgba8UnormSrgb, TextureFormat::Rgba8Unorm),
+];
+
+/// Which of a surface's formats to draw in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormatRequest {
+    Preferred,
+    /// The sRGB or linear variant of the preferred format
+    Srgb(bool),
+    Exact(TextureFormat),
+}
+
+/// The sRGB (or linear) variant of a format.
+/// Formats without one are returned as they are.
+fn with_srgb(format: TextureFormat, srgb: bool) -> TextureFormat {
+    SRGB_PAIRS
+        .iter()
+        .find(|(with, without)| format == *with || format == *without)
+        .map(|(with, without)| if srgb { *with } else { *without })
+        .unwrap_or(format)
+}
+
+pub fn is_srgb(format: TextureFormat) -> bool {
+    format.describe().srgb
+}
+
+/// The formats a surface preferring `preferred` offers, preferred first.
+///
+/// wgpu only tells us the preferred format, so the others are what `backend`
+/// reports for every surface, or the preferred format's sRGB and linear variants
+/// where that depends on the driver.
+fn surface_formats(backend: Backend, preferred: TextureFormat) -> Vec<TextureFormat> {
+    let mut formats = vec![preferred];
+    let others = match backend {
+        Backend::Dx12 => SRGB_PAIRS
+            .iter()
+            .flat_map(|(srgb, linear)| [*srgb, *linear])
+            .collect(),
+        Backend::Metal => vec![TextureFormat::Bgra8UnormSrgb, TextureFormat::Bgra8Unorm],
+        // The context is made either for sRGB or for linear output
+        Backend::Gl => SRGB_PAIRS
+            .iter()
+            .flat_map(|(srgb, linear)| [*srgb, *linear])
+            .filter(|format| is_srgb(*format) == is_srgb(preferred))
+            .collect(),
+        _ => vec![with_srgb(preferred, true), with_srgb(preferred, false)],
+    };
+
+    for format in others {
+        if !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+
+    formats
+}
+
+/// The present modes `backend` may offer, in the order they are cycled through.
+/// Where the surface lacks one after all, wgpu presents with Fifo instead.
+fn present_modes(backend: Backend) -> Vec<PresentMode> {
+    match backend {
+        Backend::Gl => vec![PresentMode::Fifo],
+        Backend::Dx12 | Backend::Metal => vec![PresentMode::Fifo, PresentMode::Immediate],
+        _ => vec![
+            PresentMode::Fifo,
+            PresentMode::Mailbox,
+            PresentMode::Immediate,
+        ],
+    }
+}
+
+/// The item after `current` in `items`, wrapping around.
+fn next<T: Copy + PartialEq>(items: &[T], current: T) -> T {
+    let index = items.iter().position(|item| *item == current);
+    items[index.map_or(0, |index| (index + 1) % items.len())]
+}
+
+/// A window and the surface drawn into it.
+///
+/// The surface configuration is kept, so changing one setting
+/// (e.g. the size on resize) leaves the others as they were chosen.
 pub struct Viewport {
     pub window: Window,
     pub surface: Surface,
+    pub config: SurfaceConfiguration,
+
+    /// What the surface can be configured with, see [`surface_formats`] and [`present_modes`]
+    formats: Vec<TextureFormat>,
+    present_modes: Vec<PresentMode>,
 }
 
 impl Viewport {
-    fn configure_surface(
-        &mut self,
-        device: &Device,
-        adapter: &Adapter,
-        width: u32,
-        height: u32,
-    ) -> Result<()> {
-        let config = SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT,
-            format: self
-                .surface
-                .get_preferred_format(adapter)
-                .wrap_err("No preferred format")?,
-            width,
-            height,
-            present_mode: PresentMode::Fifo,
-        };
-        self.surface.configure(device, &config);
-
-        Ok(())
-    }
-
+    /// Formats and present modes the surface doesn't offer fall back to
+    /// its preferred format and Fifo.
     pub fn new(
         window: Window,
-        instance: &Instance,
+        surface: Surface,
         adapter: &Adapter,
         device: &Device,
+        present_mode: PresentMode,
+        format: FormatRequest,
     ) -> Result<Self> {
-        let surface = unsafe { instance.create_surface(&window) };
+        let preferred = surface
+            .get_preferred_format(adapter)
+            .wrap_err("No preferred format")?;
+        let backend = adapter.get_info().backend;
+        let formats = surface_formats(backend, preferred);
+        let present_modes = present_modes(backend);
+
+        let format = match format {
+            FormatRequest::Preferred => Some(preferred),
+            FormatRequest::Srgb(srgb) => Some(with_srgb(preferred, srgb))
+                .filter(|format| formats.contains(format))
+                .or_else(|| {
+                    formats
+                        .iter()
+                        .copied()
+                        .find(|format| is_srgb(*format) == srgb)
+                }),
+            FormatRequest::Exact(format) => Some(format).filter(|format| formats.contains(format)),
+        };
+        let format = format.unwrap_or_else(|| {
+            warn!("Surface doesn't offer that format, using {:?}", preferred);
+            preferred
+        });
+
+        let present_mode = match present_modes.contains(&present_mode) {
+            true => present_mode,
+            false => {
+                warn!("Surface doesn't offer {:?}, using Fifo", present_mode);
+                PresentMode::Fifo
+            }
+        };
 
         let size = window.inner_size();
-        let mut new_self = Self { window, surface };
-        new_self.configure_surface(device, adapter, size.width, size.height)?;
+        let config = SurfaceConfiguration {
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode,
+        };
+
+        let new_self = Self {
+            window,
+            surface,
+            config,
+            formats,
+            present_modes,
+        };
+        new_self.configure(device);
 
         Ok(new_self)
     }
 
-    pub fn resize(&mut self, adapter: &Adapter, device: &Device, size: PhysicalSize<u32>) {
-        self.configure_surface(device, adapter, size.width, size.height)
-            .unwrap();
+    /// Does nothing while suspended, the configuration is applied once there is something to draw.
+    pub fn configure(&self, device: &Device) {
+        if !self.is_suspended() {
+            self.surface.configure(device, &self.config);
+        }
+    }
+
+    /// Minimised, or otherwise without any pixels to draw.
+    pub fn is_suspended(&self) -> bool {
+        self.config.width == 0 || self.config.height == 0
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.config.format
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        self.config.present_mode
+    }
+
+    /// The next of the surface's formats, to cycle through them.
+    pub fn next_format(&self) -> TextureFormat {
+        next(&self.formats, self.format())
+    }
+
+    /// The next of the surface's present modes, to cycle through them.
+    pub fn next_present_mode(&self) -> PresentMode {
+        next(&self.present_modes, self.present_mode())
+    }
+
+    pub fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        self.config.width = size.width;
+        self.config.height = size.height;
+        self.configure(device);
+    }
+
+    pub fn set_present_mode(&mut self, device: &Device, present_mode: PresentMode) {
+        self.config.present_mode = present_mode;
+        self.configure(device);
+    }
+
+    /// Anything drawn into the surface must be drawn in the new format from now on.
+    pub fn set_format(&mut self, device: &Device, format: TextureFormat) {
+        self.config.format = format;
+        self.configure(device);
     }
 }
@@ -1,25 +1,102 @@
-use std::{borrow::Cow, mem};
-
-use crate::{square::Square, texture_image::TextureImage, vertex::Vertex, viewport::Viewport};
+use crate::{
+    app_window::{AppWindow, Context},
+    camera_path::CameraPath,
+    globals::GlobalsUniform,
+    gpu::DeviceContext,
+    gpu_mesh::GpuMesh,
+    mesh::Mesh,
+    misc,
+    overlay::{self, OverlayBatch, OverlayRenderer, Palette},
+    pipeline::{BindLayout, PipelineBuilder, PipelineCache, Shader, SharedPipeline},
+    profiler::GpuTimer,
+    recording::{InputEvent, Target},
+    scene::Scene,
+    session::Maps,
+    text::{TextBatch, TextRenderer},
+    texture_image::{self, TextureImage},
+    vertex::Vertex,
+    viewport::Viewport,
+};
 use color_eyre::Result;
 use wgpu::*;
-use winit::event::VirtualKeyCode;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, MouseButton},
+};
+
+/// Half the width of a path handle, in logical pixels.
+const HANDLE_SIZE: f32 = 6.0;
+
+/// How many points the camera path is drawn through.
+const PATH_POINTS: usize = 64;
+
+/// Width of the camera path, in logical pixels.
+const PATH_WIDTH: f32 = 1.0;
+
+/// Handle colours, in the order they appear in the palette texture.
+#[derive(Debug, Clone, Copy)]
+enum Colour {
+    Keyframe,
+    Selected,
+    Camera,
+    Path,
+}
+
+impl Palette for Colour {
+    const COLOURS: &'static [[u8; 4]] = &[
+        [255, 220, 0, 255],
+        [255, 40, 40, 255],
+        [0, 230, 255, 255],
+        [255, 255, 255, 255],
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Camera coordinates in [-1, 1] to where they are shown on the colour map, in NDC.
+fn map_to_ndc(x: f32, y: f32) -> (f32, f32) {
+    (x * 0.5 - 0.5, y)
+}
+
+fn ndc_to_map(x: f32, y: f32) -> (f32, f32) {
+    (x * 2.0 + 1.0, y)
+}
 
 pub struct WindowExtra {
-    pub viewport: Viewport,
-    pub render_pipeline: RenderPipeline,
+    pub viewport: Option<Viewport>,
+    pub size: PhysicalSize<u32>,
+    pub texture_format: TextureFormat,
+    pub render_pipeline: SharedPipeline,
     pub left_bind_group: BindGroup,
     pub right_bind_group: BindGroup,
     pub left_image: TextureImage,
     pub right_image: TextureImage,
-    pub left_square: Square,
-    pub right_square: Square,
+    pub left_quad: Mesh,
+    pub right_quad: Mesh,
+    pub left_mesh: GpuMesh<Vertex>,
+    pub right_mesh: GpuMesh<Vertex>,
+
+    handles: OverlayRenderer<Colour>,
+
+    /// Last known cursor position within the window
+    pub cursor: Option<PhysicalPosition<f64>>,
+
+    /// The camera path keyframe being dragged
+    pub dragging: Option<usize>,
+
+    pub text: TextRenderer,
+
+    /// Key bindings to show, if help is on
+    pub help: Option<Vec<String>>,
 }
 
-fn bind_group_layout(device: &Device) -> BindGroupLayout {
-    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("Extra window bind group layout"),
-        entries: &[
+fn bind_group_layout(device: &Device) -> BindLayout {
+    BindLayout::new(
+        device,
+        "Extra window bind group layout",
+        &[
             BindGroupLayoutEntry {
                 binding: 0,
                 visibility: ShaderStages::FRAGMENT,
@@ -39,8 +116,9 @@ fn bind_group_layout(device: &Device) -> BindGroupLayout {
                 },
                 count: None,
             },
+            GlobalsUniform::default().bind_group_layout_entry(2),
         ],
-    })
+    )
 }
 
 fn bind_group(
@@ -48,6 +126,7 @@ fn bind_group(
     layout: &BindGroupLayout,
     texture_view: &TextureView,
     sampler: &Sampler,
+    globals_buffer: &Buffer,
 ) -> BindGroup {
     device.create_bind_group(&BindGroupDescriptor {
         label: Some("Extra window bind group"),
@@ -61,128 +140,99 @@ fn bind_group(
                 binding: 1,
                 resource: BindingResource::Sampler(sampler),
             },
+            BindGroupEntry {
+                binding: 2,
+                resource: globals_buffer.as_entire_binding(),
+            },
         ],
     })
 }
 
-fn pipeline_layout(device: &Device, bind_group_layout: &BindGroupLayout) -> PipelineLayout {
-    device.create_pipeline_layout(&PipelineLayoutDescriptor {
-        label: Some("Extra window pipeline layout"),
-        bind_group_layouts: &[bind_group_layout],
-        push_constant_ranges: &[],
-    })
-}
-
-fn render_pipeline(
-    device: &Device,
-    pipeline_layout: &PipelineLayout,
-    format: &TextureFormat,
-) -> RenderPipeline {
-    let shader = device.create_shader_module(&ShaderModuleDescriptor {
-        label: Some("Extra shader"),
-        source: ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/extra.wgsl"))),
-    });
-
-    device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: Some("Extra render pipeline"),
-        layout: Some(pipeline_layout),
-        vertex: VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: &[VertexBufferLayout {
-                array_stride: mem::size_of::<Vertex>() as BufferAddress,
-                step_mode: VertexStepMode::Vertex,
-                attributes: &[
-                    VertexAttribute {
-                        format: VertexFormat::Float32x2,
-                        offset: 0,
-                        shader_location: 0,
-                    },
-                    VertexAttribute {
-                        format: VertexFormat::Float32x2,
-                        offset: mem::size_of::<[f32; 2]>() as u64,
-                        shader_location: 1,
-                    },
-                ],
-            }],
+/// Textured meshes, bound with [`bind_group_layout`].
+fn render_pipeline(bind_group_layout: &BindLayout, format: TextureFormat) -> PipelineBuilder<'_> {
+    PipelineBuilder::new(
+        "Extra render pipeline",
+        Shader::Static {
+            label: "Extra shader",
+            source: include_str!("shaders/extra.wgsl"),
         },
-        primitive: PrimitiveState::default(),
-        depth_stencil: None,
-        fragment: Some(FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[format.to_owned().into()],
-        }),
-        multisample: MultisampleState::default(),
-    })
-}
-
-fn sampler(device: &Device) -> Sampler {
-    device.create_sampler(&SamplerDescriptor {
-        label: Some("Extra sampler"),
-        address_mode_u: AddressMode::ClampToEdge,
-        address_mode_v: AddressMode::ClampToEdge,
-        address_mode_w: AddressMode::ClampToEdge,
-        mag_filter: FilterMode::Nearest,
-        min_filter: FilterMode::Nearest,
-        mipmap_filter: FilterMode::Nearest,
-        ..Default::default()
-    })
+        format,
+    )
+    .layout("Extra window pipeline layout", &[bind_group_layout])
+    .vertex::<Vertex>()
 }
 
 impl WindowExtra {
+    /// Without a viewport the window is headless,
+    /// and can only be drawn with [`WindowExtra::render_to`].
     pub fn new(
-        viewport: Viewport,
-        device: &Device,
-        queue: &Queue,
+        viewport: Option<Viewport>,
+        size: PhysicalSize<u32>,
+        context: DeviceContext,
         texture_format: &TextureFormat,
+        maps: &Maps,
     ) -> Result<Self> {
-        let layout = bind_group_layout(device);
-
-        let left_image = TextureImage::new_from_path(
-            "aztec diffuse image",
+        let DeviceContext {
             device,
-            concat!(env!("CARGO_MANIFEST_DIR"), "/src/aztec-diffuse.png"),
-        )?;
+            queue,
+            pipelines,
+            globals_buffer,
+        } = context;
+        let layout = bind_group_layout(device);
 
-        let right_image = TextureImage::new_from_path(
-            "aztec height image",
-            device,
-            concat!(env!("CARGO_MANIFEST_DIR"), "/src/aztec-height.png"),
-        )?;
+        let left_image =
+            TextureImage::new_from_path("aztec diffuse image", device, &maps.diffuse_map)?;
 
-        let sampler = sampler(device);
+        let right_image =
+            TextureImage::new_from_path("aztec height image", device, &maps.height_map)?;
 
-        let left_bind_group = bind_group(device, &layout, &left_image.texture_view, &sampler);
+        let sampler = texture_image::nearest_sampler(device);
 
-        let right_bind_group = bind_group(device, &layout, &right_image.texture_view, &sampler);
+        let left_bind_group = bind_group(
+            device,
+            &layout,
+            &left_image.texture_view,
+            &sampler,
+            globals_buffer,
+        );
 
-        let pipeline_layout = pipeline_layout(device, &layout);
-        let render_pipeline = render_pipeline(device, &pipeline_layout, texture_format);
+        let right_bind_group = bind_group(
+            device,
+            &layout,
+            &right_image.texture_view,
+            &sampler,
+            globals_buffer,
+        );
 
-        let left_square = Square::new_from_vertices([
-            Vertex::new(-1.0, 1.0, 0.0, 0.0),
-            Vertex::new(0.0, 1.0, 1.0, 0.0),
-            Vertex::new(0.0, -1.0, 1.0, 1.0),
-            Vertex::new(-1.0, -1.0, 0.0, 1.0),
-        ]);
+        let render_pipeline = render_pipeline(&layout, *texture_format).build(device, pipelines)?;
 
-        let right_square = Square::new_from_vertices([
-            Vertex::new(0.0, 1.0, 0.0, 0.0),
-            Vertex::new(1.0, 1.0, 1.0, 0.0),
-            Vertex::new(1.0, -1.0, 1.0, 1.0),
-            Vertex::new(0.0, -1.0, 0.0, 1.0),
-        ]);
+        let left_quad = Mesh::quad([-1.0, 1.0], [0.0, -1.0]);
+        let right_quad = Mesh::quad([0.0, 1.0], [1.0, -1.0]);
 
-        let new_self = Self {
+        let mut new_self = Self {
             viewport,
+            size,
+            texture_format: *texture_format,
             render_pipeline,
             left_bind_group,
             right_bind_group,
             left_image,
             right_image,
-            left_square,
-            right_square,
+            left_quad,
+            right_quad,
+            left_mesh: GpuMesh::new(device, "Left map"),
+            right_mesh: GpuMesh::new(device, "Right map"),
+            handles: OverlayRenderer::new(
+                "Path handles",
+                device,
+                queue,
+                pipelines,
+                texture_format,
+            )?,
+            cursor: None,
+            dragging: None,
+            text: TextRenderer::new(device, queue, pipelines, texture_format)?,
+            help: None,
         };
 
         new_self.push_resources(device, queue)?;
@@ -190,33 +240,114 @@ impl WindowExtra {
         Ok(new_self)
     }
 
-    pub fn handle_key(&mut self, _key: VirtualKeyCode) {
-        // use winit::event::VirtualKeyCode::*;
-        // match key {
-        //     _ => {}
-        // }
+    /// Moves the dragged keyframe, if any.
+    /// Returns true if the path changed.
+    fn handle_cursor_moved(
+        &mut self,
+        position: PhysicalPosition<f64>,
+        path: &mut CameraPath,
+    ) -> bool {
+        self.cursor = Some(position);
+
+        match self.dragging {
+            Some(index) if index < path.keyframes.len() => {
+                let (x, y) = misc::cursor_to_ndc(self.size, position);
+                let (x, y) = ndc_to_map(x, y);
+                path.move_keyframe(index, x, y);
+
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Left click grabs the keyframe handle under the cursor.
+    fn handle_mouse_button(&mut self, button: MouseButton, state: ElementState, scene: &mut Scene) {
+        if button != MouseButton::Left {
+            return;
+        }
+
+        if state == ElementState::Released {
+            self.dragging = None;
+            return;
+        }
+
+        let cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => return,
+        };
 
-        // self.viewport.window.request_redraw();
+        let nearest = overlay::nearest_handle(
+            scene
+                .camera_path
+                .keyframes
+                .iter()
+                .map(|keyframe| map_to_ndc(keyframe.x, keyframe.y)),
+            cursor,
+            self.size,
+            self.scale_factor(),
+        );
+
+        self.dragging = nearest;
+        scene.selected_keyframe = nearest;
     }
 
-    fn push_resources(&self, _device: &Device, queue: &Queue) -> Result<()> {
+    /// A line along the camera path on the colour map, square handles on it
+    /// for the path's keyframes and a handle for the camera.
+    fn handles(&self, scene: &Scene) -> OverlayBatch<Colour> {
+        let (path, camera) = (&scene.camera_path, &scene.camera);
+        let length = path.length();
+        let mut handles = OverlayBatch::new(self.size, self.scale_factor());
+
+        let points = (0..=PATH_POINTS)
+            .filter(|_| length > 0.0)
+            .filter_map(|point| path.sample(length * point as f32 / PATH_POINTS as f32))
+            .map(|keyframe| map_to_ndc(keyframe.x, keyframe.y))
+            .collect::<Vec<_>>();
+        handles.line_strip(&points, PATH_WIDTH, Colour::Path);
+
+        for (index, keyframe) in path.keyframes.iter().enumerate() {
+            let colour = if Some(index) == scene.selected_keyframe {
+                Colour::Selected
+            } else {
+                Colour::Keyframe
+            };
+            handles.square(map_to_ndc(keyframe.x, keyframe.y), HANDLE_SIZE, colour);
+        }
+
+        handles.square(
+            map_to_ndc(camera.x, camera.y),
+            HANDLE_SIZE * 0.7,
+            Colour::Camera,
+        );
+
+        handles
+    }
+
+    fn push_resources(&mut self, device: &Device, queue: &Queue) -> Result<()> {
         self.left_image.write(queue);
         self.right_image.write(queue);
 
+        // The map quads don't change after this
+        for (mesh, quad) in [
+            (&mut self.left_mesh, &self.left_quad),
+            (&mut self.right_mesh, &self.right_quad),
+        ] {
+            mesh.upload_mesh(device, queue, quad);
+        }
+
         Ok(())
     }
 
     fn render_extra(
         &self,
-        square: &Square,
+        label: &str,
+        mesh: &GpuMesh<Vertex>,
         bind_group: &BindGroup,
-        device: &Device,
         encoder: &mut CommandEncoder,
         texture_view: &TextureView,
     ) {
-        let index_buffer = square.index_buffer(device);
-        let vertex_buffer = square.vertex_buffer(device);
-
+        encoder.push_debug_group(label);
         {
             let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
                 label: Some("Extra render pass"),
@@ -231,43 +362,134 @@ impl WindowExtra {
                 depth_stencil_attachment: None,
             });
 
-            // TODO: Check out debug group, debug marker calls etc.
             rpass.set_pipeline(&self.render_pipeline);
             rpass.set_bind_group(0, bind_group, &[]);
-            rpass.set_index_buffer(index_buffer.slice(..), IndexFormat::Uint16);
-            rpass.set_vertex_buffer(0, vertex_buffer.slice(..));
-            rpass.draw_indexed(0..square.indices.len() as u32, 0, 0..1);
+            rpass.insert_debug_marker("Draw map square");
+            mesh.draw(&mut rpass);
+        }
+        encoder.pop_debug_group();
+    }
+}
+
+impl AppWindow for WindowExtra {
+    fn target(&self) -> Target {
+        Target::Extra
+    }
+
+    fn viewport(&self) -> Option<&Viewport> {
+        self.viewport.as_ref()
+    }
+
+    fn viewport_mut(&mut self) -> Option<&mut Viewport> {
+        self.viewport.as_mut()
+    }
+
+    fn into_viewport(self: Box<Self>) -> Option<Viewport> {
+        self.viewport
+    }
+
+    fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    fn texture_format(&self) -> TextureFormat {
+        self.texture_format
+    }
+
+    fn set_texture_format(
+        &mut self,
+        device: &Device,
+        pipelines: &PipelineCache,
+        format: TextureFormat,
+    ) -> Result<()> {
+        let layout = bind_group_layout(device);
+        self.render_pipeline = render_pipeline(&layout, format).build(device, pipelines)?;
+        self.handles
+            .set_texture_format(device, pipelines, &format)?;
+        self.text.set_texture_format(device, pipelines, &format)?;
+        self.texture_format = format;
+
+        Ok(())
+    }
+
+    fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        if let Some(viewport) = &mut self.viewport {
+            viewport.resize(device, size);
+        }
+        self.size = size;
+    }
+
+    fn handle_input(&mut self, event: InputEvent, context: &mut Context) -> bool {
+        match event {
+            InputEvent::CursorMoved(position) => {
+                self.handle_cursor_moved(position, &mut context.scene.camera_path)
+            }
+            InputEvent::CursorLeft => {
+                self.cursor = None;
+                true
+            }
+            InputEvent::MouseButton { button, state } => {
+                self.handle_mouse_button(button, state, context.scene);
+                true
+            }
+            _ => false,
         }
     }
 
-    pub fn render(&self, device: &Device, queue: &Queue) -> Result<()> {
-        let surface_texture = self.viewport.surface.get_current_frame()?.output;
-        let texture_view = surface_texture
-            .texture
-            .create_view(&TextureViewDescriptor::default());
+    fn set_help(&mut self, lines: Option<Vec<String>>) {
+        self.help = lines;
+    }
+
+    fn clear_input(&mut self) {
+        self.cursor = None;
+        self.dragging = None;
+    }
 
+    fn encode(
+        &mut self,
+        context: DeviceContext,
+        scene: &Scene,
+        texture_view: &TextureView,
+        timer: Option<&GpuTimer>,
+    ) -> CommandBuffer {
+        let DeviceContext { device, queue, .. } = context;
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
             label: Some("Extra command encoder"),
         });
 
-        self.render_extra(
-            &self.left_square,
-            &self.left_bind_group,
-            device,
-            &mut encoder,
-            &texture_view,
-        );
-
-        self.render_extra(
-            &self.right_square,
-            &self.right_bind_group,
-            device,
-            &mut encoder,
-            &texture_view,
-        );
+        GpuTimer::scope(timer, &mut encoder, "Extra maps", |encoder| {
+            self.render_extra(
+                "Left map",
+                &self.left_mesh,
+                &self.left_bind_group,
+                encoder,
+                texture_view,
+            );
+
+            self.render_extra(
+                "Right map",
+                &self.right_mesh,
+                &self.right_bind_group,
+                encoder,
+                texture_view,
+            );
+        });
 
-        queue.submit(Some(encoder.finish()));
+        let handles = self.handles(scene);
+        GpuTimer::scope(timer, &mut encoder, "Extra handles", |encoder| {
+            self.handles
+                .render(device, queue, encoder, texture_view, &handles)
+        });
+        let mut text = TextBatch::new(self.size, self.scale_factor());
+        if let Some(help) = &self.help {
+            text.draw_help(help);
+        }
+        GpuTimer::scope(timer, &mut encoder, "Extra text", |encoder| {
+            self.text
+                .render(device, queue, encoder, texture_view, &text)
+        });
+        GpuTimer::resolve(timer, &mut encoder);
 
-        Ok(())
+        encoder.finish()
     }
 }
@@ -1,45 +1,118 @@
-use std::{
-    borrow::Cow,
-    f32::consts::FRAC_PI_3,
-    mem,
-    path::{Path, PathBuf},
-};
+use std::path::{Path, PathBuf};
 
+#[cfg(feature = "inspector")]
+use crate::inspector::Inspector;
 use crate::{
-    camera::Camera,
-    misc::Direction,
-    radar::{self, RadarUniform},
+    app_window::{AppWindow, Context},
+    bindings::{Action, MouseSettings},
+    globals::GlobalsUniform,
+    gpu::DeviceContext,
+    gpu_mesh::GpuMesh,
+    mesh::Topology,
+    misc::{self, Direction},
+    overlay::{self, OverlayBatch, OverlayRenderer, Palette},
+    pipeline::{BindLayout, PipelineBuilder, PipelineCache, Shader, SharedPipeline},
+    profiler::GpuTimer,
+    radar::RadarUniform,
+    recording::{InputEvent, Target},
     resolution::ResolutionUniform,
+    resources::{self, Tracked},
+    scene::Scene,
+    session::Maps,
     square::Square,
+    text::{Align, TextBatch, TextRenderer, TextStyle},
     texture_image::TextureImage,
-    vertex::{Vertex, VertexSelected},
+    vertex::Vertex,
     viewport::Viewport,
 };
 use color_eyre::Result;
 use wgpu::*;
-use winit::event::VirtualKeyCode;
+#[cfg(feature = "inspector")]
+use winit::event::Event;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, MouseButton, MouseScrollDelta},
+};
+
+/// The smallest vertex step size, and how much it changes per key press.
+pub const DIFF: f32 = 0.01;
+
+/// How much the field of view changes per key press or wheel notch, radians.
+const FOV_STEP: f32 = std::f32::consts::FRAC_PI_8 / 2.0;
+
+/// Scale for touchpads and other devices scrolling by pixels rather than lines,
+/// in logical pixels.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// Half the width of a vertex handle, in logical pixels.
+const HANDLE_SIZE: f32 = 5.0;
+
+/// Space between a handle and its coordinates, in logical pixels.
+const READOUT_OFFSET: f32 = 10.0;
+
+const READOUT_STYLE: TextStyle = TextStyle {
+    size: 13.0,
+    colour: [1.0, 1.0, 1.0, 1.0],
+    background: Some([0.0, 0.0, 0.0, 0.6]),
+    align: Align::Left,
+};
+
+/// Handle colours, in the order they appear in the palette texture.
+#[derive(Debug, Clone, Copy)]
+enum Colour {
+    Vertex,
+    Selected,
+}
 
-const DIFF: f32 = 0.01;
+impl Palette for Colour {
+    const COLOURS: &'static [[u8; 4]] = &[[255, 220, 0, 255], [255, 40, 40, 255]];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
 
 pub struct WindowMain {
-    pub viewport: Viewport,
-    pub square: Square,
-    pub render_pipeline: RenderPipeline,
+    pub viewport: Option<Viewport>,
+    pub size: PhysicalSize<u32>,
+    pub render_pipeline: SharedPipeline,
+
+    /// What the render pipeline draws, following the square's mesh
+    primitive: (Topology, IndexFormat),
+
     pub bind_group: BindGroup,
+    #[allow(dead_code)]
     pub image: TextureImage,
-    pub displace_amount: f32,
-    pub camera: Camera,
     pub texture_format: TextureFormat,
     pub shader_path: PathBuf,
 
-    pub vertex_buffer: Buffer,
-    pub index_buffer: Buffer,
+    pub square_mesh: GpuMesh<Vertex>,
+
+    handles: OverlayRenderer<Colour>,
 
-    pub radar: RadarUniform,
-    pub radar_buffer: Buffer,
+    pub radar_buffer: Tracked<Buffer>,
 
     pub resolution: ResolutionUniform,
-    pub resolution_buffer: Buffer,
+    pub resolution_buffer: Tracked<Buffer>,
+
+    /// Last known cursor position within the window
+    pub cursor: Option<PhysicalPosition<f64>>,
+
+    /// Whether dragging the mouse rotates the camera
+    pub looking: bool,
+
+    pub cursor_grabbed: bool,
+
+    /// The square's vertex being dragged
+    pub dragging: Option<usize>,
+
+    pub text: TextRenderer,
+
+    /// Key bindings to show, if help is on
+    pub help: Option<Vec<String>>,
+
+    #[cfg(feature = "inspector")]
+    pub inspector: Inspector,
 }
 
 // fn sampler(device: &Device) -> Sampler {
@@ -59,7 +132,8 @@ fn bind_group_layout(
     device: &Device,
     radar: &RadarUniform,
     resolution: &ResolutionUniform,
-) -> BindGroupLayout {
+    globals: &GlobalsUniform,
+) -> BindLayout {
     // device.create_bind_group_layout(&BindGroupLayoutDescriptor {
     //     label: Some("Main bind group layout"),
     //     entries: &[
@@ -84,24 +158,15 @@ fn bind_group_layout(
     //         },
     //     ],
     // })
-    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-        label: Some("Radar layout"),
-        entries: &[
+    BindLayout::new(
+        device,
+        "Radar layout",
+        &[
             radar.bind_group_layout_entry(0),
-            resolution.bind_group_layout_entry(1)
-            // BindGroupLayoutEntry {
-            //     binding: 1,
-            //     visibility: ShaderStages::FRAGMENT,
-            //     ty: BindingType::Buffer {
-            //         ty: BufferBindingType::Uniform,
-            //         has_dynamic_offset: false,
-            //         min_binding_size: BufferSize::new(mem::size_of::<[f32; 2]>() as u64),
-            //     },
-            //     count: None,
-            // },
+            resolution.bind_group_layout_entry(1),
+            globals.bind_group_layout_entry(2),
         ],
-        // entries,
-    })
+    )
 }
 
 fn bind_group(
@@ -113,6 +178,7 @@ fn bind_group(
     radar_buffer: &Buffer,
     // resolution: &ResolutionUniform,
     resolution_buffer: &Buffer,
+    globals_buffer: &Buffer,
 ) -> BindGroup {
     // device.create_bind_group(&BindGroupDescriptor {
     //     label: Some("Main window bind group"),
@@ -143,6 +209,10 @@ fn bind_group(
                 binding: 1,
                 resource: resolution_buffer.as_entire_binding(),
             },
+            BindGroupEntry {
+                binding: 2,
+                resource: globals_buffer.as_entire_binding(),
+            },
         ],
     })
 }
@@ -156,52 +226,24 @@ fn bind_group(
 //     })
 // }
 
-fn pipeline_layout(device: &Device, bind_group_layout: &BindGroupLayout) -> PipelineLayout {
-    device.create_pipeline_layout(&PipelineLayoutDescriptor {
-        label: Some("Main pipeline layout"),
-        bind_group_layouts: &[bind_group_layout],
-        push_constant_ranges: &[],
-    })
-}
-
+/// Draws the square with the radar shader, which hot reloads can change.
 fn render_pipeline(
     device: &Device,
-    shader: &ShaderModule,
-    pipeline_layout: &PipelineLayout,
-    format: &TextureFormat,
-) -> RenderPipeline {
-    device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: Some("Main render pipeline"),
-        layout: Some(pipeline_layout),
-        vertex: VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: &[VertexBufferLayout {
-                array_stride: mem::size_of::<Vertex>() as BufferAddress,
-                step_mode: VertexStepMode::Vertex,
-                attributes: &[
-                    VertexAttribute {
-                        format: VertexFormat::Float32x2,
-                        offset: 0,
-                        shader_location: 0,
-                    },
-                    VertexAttribute {
-                        format: VertexFormat::Float32x2,
-                        offset: mem::size_of::<[f32; 2]>() as u64,
-                        shader_location: 1,
-                    },
-                ],
-            }],
-        },
-        primitive: PrimitiveState::default(),
-        depth_stencil: None,
-        fragment: Some(FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[format.to_owned().into()],
-        }),
-        multisample: MultisampleState::default(),
-    })
+    pipelines: &PipelineCache,
+    shader_path: &Path,
+    bind_group_layout: &BindLayout,
+    format: TextureFormat,
+    (topology, index_format): (Topology, IndexFormat),
+) -> Result<SharedPipeline> {
+    PipelineBuilder::new(
+        "Main render pipeline",
+        Shader::File(shader_path.to_path_buf()),
+        format,
+    )
+    .layout("Main pipeline layout", &[bind_group_layout])
+    .vertex::<Vertex>()
+    .topology(topology, index_format)
+    .build(device, pipelines)
 }
 
 impl WindowMain {
@@ -230,158 +272,465 @@ impl WindowMain {
     //     }
     // }
 
+    /// Without a viewport the window is headless,
+    /// and can only be drawn with [`WindowMain::render_to`].
     pub fn new(
-        viewport: Viewport,
-        device: &Device,
-        _queue: &Queue,
+        viewport: Option<Viewport>,
+        size: PhysicalSize<u32>,
+        context: DeviceContext,
         texture_format: &TextureFormat,
+        maps: &Maps,
     ) -> Result<Self> {
+        let DeviceContext {
+            device,
+            queue,
+            pipelines,
+            globals_buffer,
+        } = context;
         // let bind_group_layout = bind_group_layout(device);
         let texture_format = *texture_format;
-        let radar = radar::RadarUniform {
-            fov: [FRAC_PI_3, 0.0],
-            view_dir: [0.0, 1.0],
-            position: [0.0, 0.0],
-        };
+        let radar = RadarUniform::default();
         // let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
         //     label: Some("Radar layout"),
         //     entries: &[radar.bind_group_layout_entry(0)],
         // });
 
-        let size = 512;
-        let width = size;
-        let height = size;
+        let image_size = 512;
+        let width = image_size;
+        let height = image_size;
 
         // 4 bytes per point: rgba
         let data: Vec<u8> = vec![0; width * height * 4];
         let image = TextureImage::new("Main texture image", device, width, height, &data)?;
-        let camera = Camera::default();
 
-        let s = viewport.window.inner_size();
-        let resolution = ResolutionUniform {
-            resolution: [s.width as f32, s.height as f32],
-        };
+        let scale_factor = viewport
+            .as_ref()
+            .map_or(1.0, |viewport| viewport.window.scale_factor());
+        let resolution = ResolutionUniform::new(size, scale_factor);
 
-        let radar_buffer = device.create_buffer(&radar.buffer_descriptor());
-        let resolution_buffer = device.create_buffer(&resolution.buffer_descriptor());
+        let radar_buffer = resources::create_buffer(device, &radar.buffer_descriptor());
+        let resolution_buffer = resources::create_buffer(device, &resolution.buffer_descriptor());
 
-        let bind_group_layout = bind_group_layout(device, &radar, &resolution);
+        let bind_group_layout =
+            bind_group_layout(device, &radar, &resolution, &GlobalsUniform::default());
         let bind_group = bind_group(
             device,
             &bind_group_layout,
             &radar_buffer,
             &resolution_buffer,
+            globals_buffer,
         );
 
-        let pipeline_layout = pipeline_layout(device, &bind_group_layout);
-        let shader_path = PathBuf::from(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/src/shaders/radar.wgsl"
-        ));
-        let shader = Self::load_shader(&shader_path, device)?;
-
-        let render_pipeline = render_pipeline(device, &shader, &pipeline_layout, &texture_format);
-
-        let square = Square::default();
-
-        let displace_amount = 0.05;
+        let shader_path = maps.shader.clone();
+        // Rebuilt on the first frame if the square's mesh is drawn otherwise
+        let primitive = (Topology::TriangleList, IndexFormat::Uint16);
+        let render_pipeline = render_pipeline(
+            device,
+            pipelines,
+            &shader_path,
+            &bind_group_layout,
+            texture_format,
+            primitive,
+        )?;
 
         // queue.write_buffer(&radar_buffer, 0, bytemuck::bytes_of(&radar));
         // queue.write_buffer(&resolution_buffer, 0, bytemuck::bytes_of(&resolution));
-        let vertex_buffer = square.vertex_buffer(device);
-        let index_buffer = square.index_buffer(device);
 
         Ok(Self {
             viewport,
-            square,
+            size,
             render_pipeline,
+            primitive,
             bind_group,
             image,
-            displace_amount,
-            camera,
             texture_format,
             shader_path,
-            vertex_buffer,
-            index_buffer,
-            radar,
+            // Uploaded from the scene's square whenever it changes
+            square_mesh: GpuMesh::new(device, "Main square"),
+            handles: OverlayRenderer::new(
+                "Vertex handles",
+                device,
+                queue,
+                pipelines,
+                &texture_format,
+            )?,
             radar_buffer,
             resolution,
             resolution_buffer,
+            cursor: None,
+            looking: false,
+            cursor_grabbed: false,
+            dragging: None,
+            text: TextRenderer::new(device, queue, pipelines, &texture_format)?,
+            help: None,
+            #[cfg(feature = "inspector")]
+            inspector: Inspector::new(
+                device,
+                pipelines,
+                &texture_format,
+                size,
+                scale_factor,
+                maps,
+            )?,
         })
     }
 
-    pub fn handle_key(&mut self, key: VirtualKeyCode) {
-        use winit::event::VirtualKeyCode::*;
-        match key {
-            F1 => self.displace_amount = f32::max(DIFF, self.displace_amount - DIFF),
-            F2 => self.displace_amount += DIFF,
-
-            F3 => {
-                self.camera.fov = f32::max(
-                    std::f32::consts::FRAC_PI_8,
-                    self.camera.fov - std::f32::consts::FRAC_PI_8 / 2.0,
-                )
+    pub fn set_cursor_grab(&mut self, grab: bool) {
+        let window = match &self.viewport {
+            Some(viewport) => &viewport.window,
+            None => return,
+        };
+
+        if let Err(e) = window.set_cursor_grab(grab) {
+            eprintln!("Could not grab cursor: {}", e);
+            return;
+        }
+        window.set_cursor_visible(!grab);
+        self.cursor_grabbed = grab;
+    }
+
+    fn handle_cursor_moved(
+        &mut self,
+        position: PhysicalPosition<f64>,
+        scene: &mut Scene,
+        mouse: &MouseSettings,
+    ) {
+        if let (Some(last), true) = (self.cursor, self.looking && !self.cursor_grabbed) {
+            // Look as far per inch of mouse movement on any monitor
+            let delta = PhysicalPosition::new(position.x - last.x, position.y - last.y)
+                .to_logical::<f32>(self.scale_factor());
+            look(scene, delta.x, delta.y, mouse);
+        }
+
+        self.cursor = Some(position);
+
+        if let Some(index) = self.dragging {
+            let (x, y) = misc::cursor_to_ndc(self.size, position);
+            let grid = Some(scene.displace_amount).filter(|_| scene.snap);
+            scene.square.move_vertex(index, x, y, grid);
+        }
+    }
+
+    /// Left click grabs the vertex handle under the cursor, while editing.
+    fn handle_mouse_button(&mut self, button: MouseButton, state: ElementState, scene: &mut Scene) {
+        match button {
+            MouseButton::Right => self.looking = state == ElementState::Pressed,
+            MouseButton::Left if state == ElementState::Released => self.dragging = None,
+            MouseButton::Left if scene.editing => {
+                self.dragging = self.vertex_under_cursor(scene);
+                if let Some(index) = self.dragging {
+                    scene.square.set_selected(index);
+                }
             }
-            F4 => {
-                self.camera.fov = f32::min(
-                    std::f32::consts::PI,
-                    self.camera.fov + std::f32::consts::FRAC_PI_8 / 2.0,
-                )
+            _ => {}
+        }
+    }
+
+    /// The square's vertex nearest to the cursor, if its handle is within reach.
+    ///
+    /// Nearest in window pixels rather than NDC, which would favour vertices
+    /// along the window's longer side.
+    fn vertex_under_cursor(&self, scene: &Scene) -> Option<usize> {
+        overlay::nearest_handle(
+            scene
+                .square
+                .mesh
+                .vertices()
+                .iter()
+                .map(|vertex| (vertex.pos[0], vertex.pos[1])),
+            self.cursor?,
+            self.size,
+            self.scale_factor(),
+        )
+    }
+
+    fn handle_mouse_wheel(&mut self, delta: MouseScrollDelta, scene: &mut Scene) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => {
+                position.to_logical::<f32>(self.scale_factor()).y / PIXELS_PER_LINE
             }
+        };
 
-            Key1 | Numpad1 => self.square.set_selected(VertexSelected::One),
-            Key2 | Numpad2 => self.square.set_selected(VertexSelected::Two),
-            Key3 | Numpad3 => self.square.set_selected(VertexSelected::Three),
-            Key4 | Numpad4 => self.square.set_selected(VertexSelected::Four),
+        // Scrolling up zooms in
+        scene.camera.zoom(-lines * FOV_STEP);
+    }
 
-            Left => self.square.displace(Direction::Left, self.displace_amount),
-            Right => self.square.displace(Direction::Right, self.displace_amount),
-            Up => self.square.displace(Direction::Up, self.displace_amount),
-            Down => self.square.displace(Direction::Down, self.displace_amount),
+    /// Square handles on the square's vertices, the selected one stands out.
+    fn handles(&self, scene: &Scene) -> OverlayBatch<Colour> {
+        let mut handles = OverlayBatch::new(self.size, self.scale_factor());
+
+        for (index, vertex) in scene.square.mesh.vertices().iter().enumerate() {
+            let colour = if index == scene.square.selected {
+                Colour::Selected
+            } else {
+                Colour::Vertex
+            };
+            handles.square((vertex.pos[0], vertex.pos[1]), HANDLE_SIZE, colour);
+        }
 
-            A => self.camera.displace(Direction::Left, self.displace_amount),
-            D => self.camera.displace(Direction::Right, self.displace_amount),
-            W => self.camera.displace(Direction::Up, self.displace_amount),
-            S => self.camera.displace(Direction::Down, self.displace_amount),
+        handles
+    }
 
-            Q => self.camera.rotate(std::f32::consts::FRAC_PI_8 / 2.0),
-            E => self.camera.rotate(-std::f32::consts::FRAC_PI_8 / 2.0),
+    /// Coordinates of the selected vertex and the one under the cursor, next to their handles.
+    fn draw_readouts(&self, scene: &Scene, text: &mut TextBatch) {
+        let mut shown = vec![scene.square.selected];
+        shown.extend(self.vertex_under_cursor(scene));
+        shown.dedup();
+
+        for index in shown {
+            let vertex = match scene.square.mesh.vertices().get(index) {
+                Some(vertex) => vertex,
+                None => continue,
+            };
+
+            let position = misc::ndc_to_cursor(self.size, (vertex.pos[0], vertex.pos[1]))
+                .to_logical::<f32>(self.scale_factor());
+            text.draw_text_styled(
+                [position.x + READOUT_OFFSET, position.y + READOUT_OFFSET],
+                &READOUT_STYLE,
+                &format!("{}: {:.3}, {:.3}", index + 1, vertex.pos[0], vertex.pos[1]),
+            );
+        }
+    }
+
+    /// The radar settings, pointed and placed according to the camera.
+    /// The camera's NDC position becomes physical pixels from the top left,
+    /// like the fragment positions it's compared to.
+    fn radar(&self, scene: &Scene) -> RadarUniform {
+        let camera = &scene.camera;
+        let position = misc::ndc_to_cursor(self.size, (camera.x, camera.y));
+
+        let mut radar = scene.radar;
+        radar.view_dir = camera.viewing_dir.as_array();
+        radar.position = [position.x as f32, position.y as f32];
+        radar.pitch = camera.pitch;
+
+        radar
+    }
+}
+
+/// Rotate the camera by a mouse movement in pixels.
+fn look(scene: &mut Scene, dx: f32, dy: f32, mouse: &MouseSettings) {
+    let dy = if mouse.invert_y { -dy } else { dy };
+
+    // Angles grow counter-clockwise, while x grows to the right
+    scene.camera.rotate(-dx * mouse.sensitivity);
+    // Screen y grows downwards
+    scene.camera.look_up(-dy * mouse.sensitivity);
+}
+
+impl AppWindow for WindowMain {
+    fn target(&self) -> Target {
+        Target::Main
+    }
+
+    fn viewport(&self) -> Option<&Viewport> {
+        self.viewport.as_ref()
+    }
+
+    fn viewport_mut(&mut self) -> Option<&mut Viewport> {
+        self.viewport.as_mut()
+    }
+
+    fn into_viewport(self: Box<Self>) -> Option<Viewport> {
+        self.viewport
+    }
+
+    fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    fn texture_format(&self) -> TextureFormat {
+        self.texture_format
+    }
+
+    fn set_texture_format(
+        &mut self,
+        device: &Device,
+        pipelines: &PipelineCache,
+        format: TextureFormat,
+    ) -> Result<()> {
+        let previous = self.texture_format;
+        self.texture_format = format;
+
+        // Keep drawing in the old format if the shader no longer builds
+        if let Err(e) = self.reload(device, pipelines) {
+            self.texture_format = previous;
+            return Err(e);
+        }
+        self.handles
+            .set_texture_format(device, pipelines, &format)?;
+        self.text.set_texture_format(device, pipelines, &format)?;
+        #[cfg(feature = "inspector")]
+        self.inspector
+            .set_texture_format(device, pipelines, &format)?;
+
+        Ok(())
+    }
+
+    fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        if let Some(viewport) = &mut self.viewport {
+            viewport.resize(device, size);
+        }
+        self.size = size;
+        self.resolution.resize(size, self.scale_factor());
+        #[cfg(feature = "inspector")]
+        self.inspector.resize(size, self.scale_factor());
+    }
+
+    fn handle_input(&mut self, event: InputEvent, context: &mut Context) -> bool {
+        let mouse = &context.bindings.mouse;
+
+        match event {
+            InputEvent::CursorMoved(position) => {
+                self.handle_cursor_moved(position, context.scene, mouse)
+            }
+            InputEvent::CursorLeft => self.cursor = None,
+            InputEvent::MouseButton { button, state } => {
+                self.handle_mouse_button(button, state, context.scene)
+            }
+            InputEvent::MouseWheel(delta) => self.handle_mouse_wheel(delta, context.scene),
+
+            // Raw mouse movement, only used while the cursor is grabbed
+            InputEvent::MouseMotion((dx, dy)) if self.cursor_grabbed => {
+                look(context.scene, dx as f32, dy as f32, mouse)
+            }
+
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn handle_action(&mut self, action: Action, context: &mut Context) {
+        let scene = &mut *context.scene;
+
+        match action {
+            Action::DisplaceAmountDown => {
+                scene.displace_amount = f32::max(DIFF, scene.displace_amount - DIFF)
+            }
+            Action::DisplaceAmountUp => scene.displace_amount += DIFF,
+
+            Action::FovNarrower => scene.camera.zoom(-FOV_STEP),
+            Action::FovWider => scene.camera.zoom(FOV_STEP),
+
+            Action::SelectVertex1 => scene.square.set_selected(0),
+            Action::SelectVertex2 => scene.square.set_selected(1),
+            Action::SelectVertex3 => scene.square.set_selected(2),
+            Action::SelectVertex4 => scene.square.set_selected(3),
+
+            Action::VertexLeft => scene
+                .square
+                .displace(Direction::Left, scene.displace_amount),
+            Action::VertexRight => scene
+                .square
+                .displace(Direction::Right, scene.displace_amount),
+            Action::VertexUp => scene.square.displace(Direction::Up, scene.displace_amount),
+            Action::VertexDown => scene
+                .square
+                .displace(Direction::Down, scene.displace_amount),
+
+            Action::ToggleVertexEditor => {
+                scene.editing = !scene.editing;
+                self.dragging = None;
+            }
+            Action::ToggleSnap => scene.snap = !scene.snap,
+            Action::SaveSurface => match scene.square.save(context.layout_path) {
+                Ok(()) => println!("Saved surface to {}", context.layout_path.display()),
+                Err(e) => eprintln!("Error saving surface: {:#?}", e),
+            },
+            Action::LoadSurface => match Square::load(context.layout_path) {
+                Ok(square) => {
+                    scene.square = square;
+                    self.dragging = None;
+                }
+                Err(e) => eprintln!("Error loading surface: {:#?}", e),
+            },
+
+            Action::ToggleCursorGrab => self.set_cursor_grab(!self.cursor_grabbed),
 
             _ => {}
         }
+    }
 
-        self.radar.view_dir = self.camera.viewing_dir.as_array();
+    fn set_help(&mut self, lines: Option<Vec<String>>) {
+        self.help = lines;
+    }
 
-        let sz = self.viewport.window.inner_size();
-        self.radar.position = [self.camera.x * sz.width as f32, self.camera.y * sz.height as f32];
+    fn clear_input(&mut self) {
+        self.cursor = None;
+        self.looking = false;
+        self.dragging = None;
 
-        self.viewport.window.request_redraw();
+        // A grabbed cursor would stay hidden in whatever has focus now
+        if self.cursor_grabbed {
+            self.set_cursor_grab(false);
+        }
     }
 
-    // pub fn push_resources(&self, queue: &Queue) -> Result<()> {
-    //     self.image.write(queue);
+    #[cfg(feature = "inspector")]
+    fn set_inspector(&mut self, shown: bool) {
+        self.inspector.shown = shown;
+    }
 
-    //     Ok(())
-    // }
+    #[cfg(feature = "inspector")]
+    fn capture_event(&mut self, event: &Event<()>) -> bool {
+        self.inspector.capture_event(event)
+    }
+
+    #[cfg(feature = "inspector")]
+    fn update_ui(&mut self, context: &mut Context) -> bool {
+        // egui would show the cursor again
+        let window = self
+            .viewport
+            .as_ref()
+            .filter(|_| !self.cursor_grabbed)
+            .map(|viewport| &viewport.window);
 
-    pub fn render(&self, device: &Device, queue: &Queue) -> Result<()> {
-        let surface_texture = self.viewport.surface.get_current_frame()?.output;
-        let texture_view = surface_texture
-            .texture
-            .create_view(&TextureViewDescriptor::default());
+        self.inspector.update(context, window)
+    }
 
+    fn encode(
+        &mut self,
+        context: DeviceContext,
+        scene: &Scene,
+        texture_view: &TextureView,
+        timer: Option<&GpuTimer>,
+    ) -> CommandBuffer {
+        let DeviceContext {
+            device,
+            queue,
+            pipelines,
+            ..
+        } = context;
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
             label: Some("Main command encoder"),
         });
 
-        // let index_buffer = self.square.index_buffer(device);
-        // let vertex_buffer = self.square.vertex_buffer(device);
+        // Only written when the square's mesh has changed since
+        self.square_mesh
+            .upload_mesh(device, queue, &scene.square.mesh);
+
+        // A mesh of lines or strips may have been loaded since the last frame
+        let mesh = &scene.square.mesh;
+        let primitive = (mesh.topology, mesh.indices().format());
+        if primitive != self.primitive {
+            let previous = std::mem::replace(&mut self.primitive, primitive);
+            if let Err(e) = self.reload(device, pipelines) {
+                eprintln!("Error building main pipeline for {:?}: {:#?}", primitive, e);
+                self.primitive = previous;
+            }
+        }
+        // Left out rather than drawn as the wrong primitives
+        let draw_square = primitive == self.primitive;
 
-        {
+        GpuTimer::scope(timer, &mut encoder, "Main radar", |encoder| {
+            encoder.push_debug_group("Main radar");
             let mut rpass = encoder.begin_render_pass(&RenderPassDescriptor {
                 label: Some("Main render pass"),
                 color_attachments: &[RenderPassColorAttachment {
-                    view: &texture_view,
+                    view: texture_view,
                     resolve_target: None,
                     ops: Operations {
                         load: LoadOp::Clear(Color {
@@ -396,57 +745,68 @@ impl WindowMain {
                 depth_stencil_attachment: None,
             });
 
-            queue.write_buffer(&self.radar_buffer, 0, bytemuck::bytes_of(&self.radar));
+            queue.write_buffer(
+                &self.radar_buffer,
+                0,
+                bytemuck::bytes_of(&self.radar(scene)),
+            );
             queue.write_buffer(
                 &self.resolution_buffer,
                 0,
                 bytemuck::bytes_of(&self.resolution),
             );
 
-            // TODO: Check out debug group, debug marker calls etc.
             rpass.set_pipeline(&self.render_pipeline);
             rpass.set_bind_group(0, &self.bind_group, &[]);
-            rpass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
-            rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            rpass.draw_indexed(0..self.square.indices.len() as u32, 0, 0..1);
-        }
-
-        queue.submit(Some(encoder.finish()));
-
-        Ok(())
-    }
-
-    fn load_shader<P: AsRef<Path>>(path: P, device: &Device) -> Result<ShaderModule> {
-        let wgsl = std::fs::read_to_string(path)?;
-        naga::front::wgsl::parse_str(&wgsl)?;
+            if draw_square {
+                rpass.insert_debug_marker("Draw radar square");
+                self.square_mesh.draw(&mut rpass);
+            }
 
-        let shader = device.create_shader_module(&ShaderModuleDescriptor {
-            label: Some("Main/radar shader"),
-            source: ShaderSource::Wgsl(Cow::Borrowed(&wgsl)),
+            drop(rpass);
+            encoder.pop_debug_group();
+        });
+        let mut text = TextBatch::new(self.size, self.scale_factor());
+        if scene.editing {
+            let handles = self.handles(scene);
+            GpuTimer::scope(timer, &mut encoder, "Main handles", |encoder| {
+                self.handles
+                    .render(device, queue, encoder, texture_view, &handles)
+            });
+            self.draw_readouts(scene, &mut text);
+        }
+        if let Some(help) = &self.help {
+            text.draw_help(help);
+        }
+        GpuTimer::scope(timer, &mut encoder, "Main text", |encoder| {
+            self.text
+                .render(device, queue, encoder, texture_view, &text)
+        });
+        #[cfg(feature = "inspector")]
+        GpuTimer::scope(timer, &mut encoder, "Main inspector", |encoder| {
+            self.inspector.render(device, queue, encoder, texture_view)
         });
+        GpuTimer::resolve(timer, &mut encoder);
 
-        Ok(shader)
+        encoder.finish()
     }
 
-    pub fn reload_shader(&mut self, device: &Device) -> Result<()> {
-        let shader = Self::load_shader(&self.shader_path, device)?;
-
-        let bind_group_layout = bind_group_layout(device, &self.radar, &self.resolution);
-        let pipeline_layout = pipeline_layout(device, &bind_group_layout);
-        let format = self.texture_format;
-
-        self.render_pipeline = render_pipeline(device, &shader, &pipeline_layout, &format);
+    fn reload(&mut self, device: &Device, pipelines: &PipelineCache) -> Result<()> {
+        let bind_group_layout = bind_group_layout(
+            device,
+            &RadarUniform::default(),
+            &self.resolution,
+            &GlobalsUniform::default(),
+        );
+        self.render_pipeline = render_pipeline(
+            device,
+            pipelines,
+            &self.shader_path,
+            &bind_group_layout,
+            self.texture_format,
+            self.primitive,
+        )?;
 
         Ok(())
     }
-
-    pub fn resize(
-        &mut self,
-        adapter: &Adapter,
-        device: &Device,
-        size: winit::dpi::PhysicalSize<u32>,
-    ) {
-        self.viewport.resize(adapter, device, size);
-        self.resolution.resize(size);
-    }
 }
@@ -0,0 +1,295 @@
+use crate::{
+    app_window::{AppWindow, Context},
+    bindings::Action,
+    camera::HEIGHT_MAX,
+    gpu::DeviceContext,
+    overlay::{OverlayBatch, OverlayRenderer, Palette},
+    pipeline::PipelineCache,
+    profile::{HeightMap, Profile},
+    profiler::GpuTimer,
+    recording::{InputEvent, Target},
+    scene::Scene,
+    session::Maps,
+    text::{Align, TextBatch, TextRenderer, TextStyle},
+    viewport::Viewport,
+};
+use color_eyre::Result;
+use wgpu::*;
+use winit::dpi::PhysicalSize;
+
+/// How many terrain columns to draw.
+const PROFILE_SAMPLES: usize = 256;
+
+/// How much the camera height changes per key press.
+const HEIGHT_STEP: f32 = 0.05;
+
+/// How much the viewing distance changes per key press.
+const VIEWING_DISTANCE_STEP: f32 = 0.05;
+
+/// Fraction of the window left empty around the plot, in NDC.
+const MARGIN: f32 = 0.05;
+
+/// Width of the line of sight, in logical pixels.
+const LINE_WIDTH: f32 = 2.0;
+
+/// Half the width of the camera marker, in logical pixels.
+const CAMERA_SIZE: f32 = 5.0;
+
+/// Distance of the labels from the window's edges, in logical pixels.
+const LABEL_MARGIN: f32 = 6.0;
+
+const TEXT: [f32; 4] = [0.85, 0.85, 0.9, 1.0];
+
+const SKY: Color = Color {
+    r: 0.02,
+    g: 0.03,
+    b: 0.08,
+    a: 1.0,
+};
+
+/// Plot colours, in the order they appear in the palette texture.
+#[derive(Debug, Clone, Copy)]
+enum Colour {
+    Visible,
+    Occluded,
+    Sight,
+    Camera,
+}
+
+impl Palette for Colour {
+    const COLOURS: &'static [[u8; 4]] = &[
+        [110, 200, 90, 255],
+        [70, 70, 95, 255],
+        [255, 220, 0, 255],
+        [0, 230, 255, 255],
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A side-on cross-section of the height map along the camera's view direction:
+/// Terrain the camera sees, terrain hidden behind it, and the line of sight.
+pub struct WindowProfile {
+    pub viewport: Option<Viewport>,
+    pub size: PhysicalSize<u32>,
+    pub texture_format: TextureFormat,
+
+    pub height_map: HeightMap,
+
+    plot: OverlayRenderer<Colour>,
+
+    pub text: TextRenderer,
+
+    /// Key bindings to show, if help is on
+    pub help: Option<Vec<String>>,
+}
+
+impl WindowProfile {
+    /// Without a viewport the window is headless,
+    /// and can only be drawn with [`AppWindow::render_to`].
+    pub fn new(
+        viewport: Option<Viewport>,
+        size: PhysicalSize<u32>,
+        context: DeviceContext,
+        texture_format: &TextureFormat,
+        maps: &Maps,
+    ) -> Result<Self> {
+        let DeviceContext {
+            device,
+            queue,
+            pipelines,
+            ..
+        } = context;
+
+        let height_map = HeightMap::new_from_path(&maps.height_map)?;
+
+        Ok(Self {
+            viewport,
+            size,
+            texture_format: *texture_format,
+            height_map,
+            plot: OverlayRenderer::new("Profile plot", device, queue, pipelines, texture_format)?,
+            text: TextRenderer::new(device, queue, pipelines, texture_format)?,
+            help: None,
+        })
+    }
+
+    /// Terrain columns, the line of sight and the camera, in NDC.
+    /// The camera is on the left edge, its viewing distance on the right.
+    fn plot(&self, scene: &Scene) -> OverlayBatch<Colour> {
+        let camera = &scene.camera;
+        let profile = Profile::new(&self.height_map, camera, PROFILE_SAMPLES);
+
+        let to_ndc = |distance: f32, height: f32| {
+            (
+                (distance / camera.viewing_distance * 2.0 - 1.0) * (1.0 - MARGIN),
+                (height / HEIGHT_MAX * 2.0 - 1.0) * (1.0 - MARGIN),
+            )
+        };
+        let mut plot = OverlayBatch::new(self.size, self.scale_factor());
+        plot.clear(SKY);
+
+        let column = camera.viewing_distance / PROFILE_SAMPLES as f32;
+        for sample in &profile.samples {
+            let (left, bottom) = to_ndc(sample.distance - column, 0.0);
+            let (right, top) = to_ndc(sample.distance, sample.terrain);
+            let colour = if sample.visible {
+                Colour::Visible
+            } else {
+                Colour::Occluded
+            };
+
+            plot.quad(
+                [(left, top), (right, top), (right, bottom), (left, bottom)],
+                colour,
+            );
+        }
+
+        let camera_position = to_ndc(0.0, camera.height);
+        let sight = to_ndc(
+            camera.viewing_distance,
+            camera.height + camera.pitch.tan() * camera.viewing_distance,
+        );
+        plot.line(camera_position, sight, LINE_WIDTH, Colour::Sight);
+        plot.square(camera_position, CAMERA_SIZE, Colour::Camera);
+
+        plot
+    }
+
+    /// What the plot shows, and the camera's height and viewing distance.
+    fn label(&self, scene: &Scene, text: &mut TextBatch) {
+        let camera = &scene.camera;
+        let scale_factor = self.scale_factor() as f32;
+        let (width, height) = (
+            self.size.width as f32 / scale_factor,
+            self.size.height as f32 / scale_factor,
+        );
+        let style = TextStyle {
+            colour: TEXT,
+            ..Default::default()
+        };
+        let bottom = height - LABEL_MARGIN - style.size;
+
+        text.draw_text_styled(
+            [width / 2.0, LABEL_MARGIN],
+            &TextStyle {
+                align: Align::Centre,
+                ..style
+            },
+            "Line of sight",
+        );
+        text.draw_text(
+            [LABEL_MARGIN, bottom],
+            style.size,
+            style.colour,
+            &format!("Height {:.2}", camera.height),
+        );
+        text.draw_text_styled(
+            [width - LABEL_MARGIN, bottom],
+            &TextStyle {
+                align: Align::Right,
+                ..style
+            },
+            &format!("Distance {:.2}", camera.viewing_distance),
+        );
+    }
+}
+
+impl AppWindow for WindowProfile {
+    fn target(&self) -> Target {
+        Target::Profile
+    }
+
+    fn viewport(&self) -> Option<&Viewport> {
+        self.viewport.as_ref()
+    }
+
+    fn viewport_mut(&mut self) -> Option<&mut Viewport> {
+        self.viewport.as_mut()
+    }
+
+    fn into_viewport(self: Box<Self>) -> Option<Viewport> {
+        self.viewport
+    }
+
+    fn size(&self) -> PhysicalSize<u32> {
+        self.size
+    }
+
+    fn texture_format(&self) -> TextureFormat {
+        self.texture_format
+    }
+
+    fn set_texture_format(
+        &mut self,
+        device: &Device,
+        pipelines: &PipelineCache,
+        format: TextureFormat,
+    ) -> Result<()> {
+        self.plot.set_texture_format(device, pipelines, &format)?;
+        self.text.set_texture_format(device, pipelines, &format)?;
+        self.texture_format = format;
+
+        Ok(())
+    }
+
+    fn resize(&mut self, device: &Device, size: PhysicalSize<u32>) {
+        if let Some(viewport) = &mut self.viewport {
+            viewport.resize(device, size);
+        }
+        self.size = size;
+    }
+
+    fn handle_input(&mut self, _event: InputEvent, _context: &mut Context) -> bool {
+        false
+    }
+
+    fn handle_action(&mut self, action: Action, context: &mut Context) {
+        let camera = &mut context.scene.camera;
+
+        match action {
+            Action::CameraHigher => camera.raise(HEIGHT_STEP),
+            Action::CameraLower => camera.raise(-HEIGHT_STEP),
+            Action::ViewFarther => camera.look_further(VIEWING_DISTANCE_STEP),
+            Action::ViewNearer => camera.look_further(-VIEWING_DISTANCE_STEP),
+            _ => {}
+        }
+    }
+
+    fn set_help(&mut self, lines: Option<Vec<String>>) {
+        self.help = lines;
+    }
+
+    fn encode(
+        &mut self,
+        context: DeviceContext,
+        scene: &Scene,
+        texture_view: &TextureView,
+        timer: Option<&GpuTimer>,
+    ) -> CommandBuffer {
+        let DeviceContext { device, queue, .. } = context;
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Profile command encoder"),
+        });
+
+        let plot = self.plot(scene);
+        GpuTimer::scope(timer, &mut encoder, "Profile plot", |encoder| {
+            self.plot
+                .render(device, queue, encoder, texture_view, &plot)
+        });
+        let mut text = TextBatch::new(self.size, self.scale_factor());
+        self.label(scene, &mut text);
+        if let Some(help) = &self.help {
+            text.draw_help(help);
+        }
+        GpuTimer::scope(timer, &mut encoder, "Profile text", |encoder| {
+            self.text
+                .render(device, queue, encoder, texture_view, &text)
+        });
+        GpuTimer::resolve(timer, &mut encoder);
+
+        encoder.finish()
+    }
+}